@@ -0,0 +1,296 @@
+//! Obstacles and the walls around the arena.
+//!
+//! Cars are treated as circles. Whenever one overlaps a wall or an obstacle it
+//! is pushed back out and its velocity is reflected off the surface it hit.
+//! Cars running into each other are pushed apart by their mass.
+
+use bevy::{math::Vec3Swizzles, prelude::*, sprite::Mesh2dHandle};
+use bevy_ggrs::Rollback;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::CarClass,
+    map::Map,
+    round::{Player, RoundEntity, Velocity},
+    tuning::CarTuning,
+};
+
+/// Cars pushed apart by a collision still count as touching.
+const TOUCH_MARGIN: f32 = 2.;
+/// Share of the speed into a surface that is kept after bouncing off it.
+const BOUNCE: f32 = 0.6;
+const OBSTACLE_COLOR: Color = Color::rgb(0.3, 0.3, 0.35);
+const CHECKPOINT_COLOR: Color = Color::rgba(0.3, 0.6, 0.9, 0.15);
+
+/// A static obstacle, positions are relative to the center of the arena.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ObstacleDef {
+    Pillar {
+        x: f32,
+        y: f32,
+        radius: f32,
+    },
+    Barrier {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        /// Counterclockwise, in degrees.
+        #[serde(default)]
+        angle: f32,
+    },
+}
+
+impl ObstacleDef {
+    fn center(&self) -> Vec2 {
+        match *self {
+            ObstacleDef::Pillar { x, y, .. } | ObstacleDef::Barrier { x, y, .. } => Vec2::new(x, y),
+        }
+    }
+
+    /// Whether the point `pos` lies inside the obstacle.
+    pub fn contains(&self, pos: Vec2) -> bool {
+        let center = self.center();
+        match *self {
+            ObstacleDef::Pillar { radius, .. } => pos.distance(center) < radius,
+            ObstacleDef::Barrier {
+                width,
+                height,
+                angle,
+                ..
+            } => {
+                let local = Mat2::from_angle(angle.to_radians()).transpose() * (pos - center);
+                local.abs().cmple(Vec2::new(width, height) * 0.5).all()
+            }
+        }
+    }
+
+    /// Moves a car of `car_radius` at `pos` out of the obstacle, returning the
+    /// normal of the surface it was pushed out of, if they overlapped at all.
+    fn push_out(&self, pos: &mut Vec2, car_radius: f32) -> Option<Vec2> {
+        let center = self.center();
+        match *self {
+            ObstacleDef::Pillar { radius, .. } => {
+                let offset = *pos - center;
+                let min_dist = radius + car_radius;
+                let dist = offset.length();
+                if dist >= min_dist {
+                    return None;
+                }
+                let normal = if dist > 0. { offset / dist } else { Vec2::Y };
+                *pos = center + normal * min_dist;
+                Some(normal)
+            }
+            ObstacleDef::Barrier {
+                width,
+                height,
+                angle,
+                ..
+            } => {
+                let half_size = Vec2::new(width, height) * 0.5;
+                let rotation = Mat2::from_angle(angle.to_radians());
+                let local = rotation.transpose() * (*pos - center);
+                let closest = local.clamp(-half_size, half_size);
+                let offset = local - closest;
+
+                let (local, normal) = if offset == Vec2::ZERO {
+                    // the center of the car made it inside, leave through the nearest side
+                    let depth = half_size - local.abs();
+                    if depth.x < depth.y {
+                        let normal = Vec2::new(local.x.signum(), 0.);
+                        let x = normal.x * (half_size.x + car_radius);
+                        (Vec2::new(x, local.y), normal)
+                    } else {
+                        let normal = Vec2::new(0., local.y.signum());
+                        let y = normal.y * (half_size.y + car_radius);
+                        (Vec2::new(local.x, y), normal)
+                    }
+                } else {
+                    let dist = offset.length();
+                    if dist >= car_radius {
+                        return None;
+                    }
+                    let normal = offset / dist;
+                    (closest + normal * car_radius, normal)
+                };
+
+                *pos = center + rotation * local;
+                Some(rotation * normal)
+            }
+        }
+    }
+}
+
+/// Only there to be drawn, the collisions go by the [`Map`].
+#[derive(Component)]
+pub struct Obstacle(pub ObstacleDef);
+
+/// Called from [`crate::round::setup_round`], the visuals are added by
+/// [`add_obstacle_visuals`] so the round can be set up without rendering.
+pub fn spawn_obstacles(commands: &mut Commands, map: &Map) {
+    for def in map.obstacles.iter() {
+        let mut transform = Transform::from_translation(def.center().extend(0.3));
+        if let ObstacleDef::Barrier { angle, .. } = def {
+            transform = transform.with_rotation(Quat::from_rotation_z(angle.to_radians()));
+        }
+        commands
+            .spawn(SpatialBundle::from_transform(transform))
+            .insert(Obstacle(def.clone()))
+            .insert(RoundEntity);
+    }
+}
+
+#[derive(Component)]
+pub struct CheckpointMarker {
+    pub index: usize,
+}
+
+/// Checkpoints are only marked on the ground for now.
+pub fn spawn_checkpoints(commands: &mut Commands, map: &Map) {
+    for (index, checkpoint) in map.checkpoints.iter().enumerate() {
+        commands
+            .spawn(SpriteBundle {
+                transform: Transform::from_xyz(checkpoint.x, checkpoint.y, 0.05),
+                sprite: Sprite {
+                    color: CHECKPOINT_COLOR,
+                    custom_size: Some(Vec2::splat(checkpoint.radius * 2.)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(CheckpointMarker { index })
+            .insert(RoundEntity);
+    }
+}
+
+pub fn add_obstacle_visuals(
+    mut commands: Commands,
+    query: Query<(Entity, &Obstacle), Added<Obstacle>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, Obstacle(def)) in query.iter() {
+        let mut entity = commands.entity(entity);
+        match *def {
+            ObstacleDef::Pillar { radius, .. } => {
+                entity.insert((
+                    Mesh2dHandle(meshes.add(shape::Circle::new(radius).into())),
+                    materials.add(OBSTACLE_COLOR.into()),
+                ));
+            }
+            ObstacleDef::Barrier { width, height, .. } => {
+                entity.insert((
+                    Sprite {
+                        color: OBSTACLE_COLOR,
+                        custom_size: Some(Vec2::new(width, height)),
+                        ..Default::default()
+                    },
+                    Handle::<Image>::default(),
+                ));
+            }
+        }
+    }
+}
+
+/// A car as far as collisions are concerned.
+struct Body {
+    pos: Vec2,
+    vel: Vec2,
+    radius: f32,
+    mass: f32,
+}
+
+/// Bounces the cars off each other, the walls and the obstacles they drove
+/// into this frame.
+pub fn bounce_players(
+    mut query: Query<(&mut Transform, &mut Velocity, &Player, &CarClass), With<Rollback>>,
+    map: Res<Map>,
+    tuning: Res<CarTuning>,
+) {
+    // query order is not the same on every peer, collisions are resolved by handle
+    let mut cars: Vec<_> = query.iter_mut().collect();
+    cars.sort_by_key(|(_, _, p, _)| p.handle);
+
+    let mut bodies: Vec<_> = cars
+        .iter()
+        .map(|(t, v, _, car)| Body {
+            pos: t.translation.xy(),
+            vel: v.0,
+            radius: car.radius(),
+            mass: tuning.class(**car).mass,
+        })
+        .collect();
+
+    for i in 0..bodies.len() {
+        let (first, rest) = bodies.split_at_mut(i + 1);
+        for other in rest.iter_mut() {
+            collide(&mut first[i], other);
+        }
+    }
+
+    // the walls have the last word, cars cannot be pushed out of the arena
+    for body in bodies.iter_mut() {
+        let bounds = map.size() * 0.5 - body.radius;
+        let walls = [
+            (Vec2::X, bounds.x),
+            (Vec2::NEG_X, bounds.x),
+            (Vec2::Y, bounds.y),
+            (Vec2::NEG_Y, bounds.y),
+        ];
+        for (normal, dist) in walls {
+            // the normal points into the arena, away from the wall at -normal * dist
+            let depth = -dist - body.pos.dot(normal);
+            if depth > 0. {
+                body.pos += normal * depth;
+                reflect(&mut body.vel, normal);
+            }
+        }
+        for obstacle in map.obstacles.iter() {
+            if let Some(normal) = obstacle.push_out(&mut body.pos, body.radius) {
+                reflect(&mut body.vel, normal);
+            }
+        }
+    }
+
+    for ((t, v, ..), body) in cars.iter_mut().zip(bodies) {
+        t.translation.x = body.pos.x;
+        t.translation.y = body.pos.y;
+        v.0 = body.vel;
+    }
+}
+
+/// Separates two overlapping cars, the lighter one moving further, and
+/// bounces them off each other.
+fn collide(a: &mut Body, b: &mut Body) {
+    let offset = b.pos - a.pos;
+    let dist = offset.length();
+    let min_dist = a.radius + b.radius;
+    if dist >= min_dist {
+        return;
+    }
+    let normal = if dist > 0. { offset / dist } else { Vec2::X };
+    let total_mass = a.mass + b.mass;
+    let overlap = min_dist - dist;
+    a.pos -= normal * overlap * (b.mass / total_mass);
+    b.pos += normal * overlap * (a.mass / total_mass);
+
+    let closing = (a.vel - b.vel).dot(normal);
+    if closing > 0. {
+        let impulse = (1. + BOUNCE) * closing / (1. / a.mass + 1. / b.mass);
+        a.vel -= normal * (impulse / a.mass);
+        b.vel += normal * (impulse / b.mass);
+    }
+}
+
+/// Whether two cars at these positions are touching.
+pub fn cars_touch(a: Vec2, a_car: CarClass, b: Vec2, b_car: CarClass) -> bool {
+    a.distance(b) < a_car.radius() + b_car.radius() + TOUCH_MARGIN
+}
+
+/// Mirrors the part of the velocity going into the surface, losing some of it.
+fn reflect(vel: &mut Vec2, normal: Vec2) {
+    let into = vel.dot(normal);
+    if into < 0. {
+        *vel -= (1. + BOUNCE) * into * normal;
+    }
+}
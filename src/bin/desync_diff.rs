@@ -0,0 +1,76 @@
+//! Compares the desync dumps two peers wrote for the same frame and reports
+//! where their states diverged.
+
+use std::{fs, process::ExitCode};
+
+use bevy_ggrs_demo::desync::{differences, DesyncDump};
+
+const USAGE: &str = "\
+Usage: desync_diff <DUMP> <DUMP>
+
+Compares two dumps from the desync directory, e.g.
+    desync_diff desync/frame-120-p1.json other/desync/frame-120-p2.json";
+
+fn read_dump(path: &str) -> Result<DesyncDump, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    serde_json::from_str(&json).map_err(|e| format!("{path} is not a desync dump: {e}"))
+}
+
+fn run(left_path: &str, right_path: &str) -> Result<bool, String> {
+    let left = read_dump(left_path)?;
+    let right = read_dump(right_path)?;
+    if left.state.frame != right.state.frame {
+        println!(
+            "warning: comparing frame {} with frame {}",
+            left.state.frame, right.state.frame
+        );
+    }
+    println!(
+        "frame {}, checksums {} and {}",
+        left.state.frame, left.local_checksum, right.local_checksum
+    );
+
+    let diffs = differences(&left.state, &right.state);
+    let Some(first) = diffs.first() else {
+        println!("no differences");
+        return Ok(false);
+    };
+
+    let entity = first
+        .path
+        .strip_prefix("entities[")
+        .and_then(|rest| rest.split_once(']'))
+        .map(|(rollback, _)| format!("rollback entity {rollback}"))
+        .unwrap_or_else(|| "a resource".to_owned());
+    println!("first divergence in {entity}: {}", first.path);
+    println!("\n{} differing fields:", diffs.len());
+    let show = |value: &Option<serde_json::Value>| match value {
+        Some(value) => value.to_string(),
+        None => "missing".to_owned(),
+    };
+    for diff in diffs.iter() {
+        println!(
+            "    {}: {} vs {}",
+            diff.path,
+            show(&diff.left),
+            show(&diff.right)
+        );
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [left, right] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::from(2);
+    };
+    match run(left, right) {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Boost pads and speed pickups.
+//!
+//! Pads boost every car driving over them, pickups are collected by the first
+//! car to touch them and come back after a while. Both are rollback entities
+//! and only count frames, so they play out the same on every peer.
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_ggrs::{AddRollbackCommandExtension, Rollback};
+
+use crate::{
+    car::CarClass,
+    map::Map,
+    round::{Player, RoundEntity},
+};
+
+/// Both the acceleration and the top speed are raised by this factor.
+pub const BOOST_FACTOR: f32 = 1.6;
+const PAD_BOOST_FRAMES: u32 = 30;
+const PICKUP_BOOST_FRAMES: u32 = 120;
+const PICKUP_RESPAWN_FRAMES: u32 = 300;
+
+const PAD_SIZE: f32 = 80.;
+const PICKUP_SIZE: f32 = 24.;
+const PAD_COLOR: Color = Color::rgb(0.25, 0.2, 0.05);
+const PICKUP_COLOR: Color = Color::rgb(0.95, 0.85, 0.2);
+
+/// Frames of boost left on a car.
+#[derive(Default, Reflect, Hash, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Boost {
+    pub frames: u32,
+}
+
+impl Boost {
+    pub fn factor(&self) -> f32 {
+        if self.frames > 0 {
+            BOOST_FACTOR
+        } else {
+            1.
+        }
+    }
+
+    /// Topping up never shortens a boost that is already running.
+    fn refill(&mut self, frames: u32) {
+        self.frames = self.frames.max(frames);
+    }
+}
+
+#[derive(Default, Reflect, Component)]
+pub struct BoostPad;
+
+/// A pickup can be collected when it is not waiting to respawn.
+#[derive(Default, Reflect, Hash, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Pickup {
+    pub respawn_frames: u32,
+}
+
+impl Pickup {
+    pub fn is_available(&self) -> bool {
+        self.respawn_frames == 0
+    }
+}
+
+fn touches(car_pos: Vec2, car: CarClass, pos: Vec2, size: f32) -> bool {
+    car_pos.distance(pos) < (size + car.length()) * 0.5
+}
+
+/// Called from [`crate::round::setup_round`].
+pub fn spawn_boosts(commands: &mut Commands, map: &Map) {
+    for pad in map.boost_pads.iter() {
+        commands
+            .spawn(SpriteBundle {
+                transform: Transform::from_xyz(pad.x, pad.y, 0.1),
+                sprite: Sprite {
+                    color: PAD_COLOR,
+                    custom_size: Some(Vec2::splat(PAD_SIZE)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(BoostPad)
+            .add_rollback()
+            .insert(RoundEntity);
+    }
+
+    for pickup in map.pickups.iter() {
+        commands
+            .spawn(SpriteBundle {
+                transform: Transform::from_xyz(pickup.x, pickup.y, 0.2)
+                    .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                sprite: Sprite {
+                    color: PICKUP_COLOR,
+                    custom_size: Some(Vec2::splat(PICKUP_SIZE)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .insert(Pickup::default())
+            .add_rollback()
+            .insert(RoundEntity);
+    }
+}
+
+/// Counts down the boosts and respawn timers, then hands out boosts to the
+/// cars touching a pad or pickup.
+pub fn update_boosts(
+    mut car_query: Query<(&Transform, &Player, &CarClass, &mut Boost), With<Rollback>>,
+    pad_query: Query<&Transform, (With<BoostPad>, With<Rollback>)>,
+    mut pickup_query: Query<(&Transform, &mut Pickup), With<Rollback>>,
+) {
+    for (.., mut boost) in car_query.iter_mut() {
+        boost.frames = boost.frames.saturating_sub(1);
+    }
+    for (_, mut pickup) in pickup_query.iter_mut() {
+        pickup.respawn_frames = pickup.respawn_frames.saturating_sub(1);
+    }
+
+    // query order is not the same on every peer, the lower handle wins a pickup
+    let mut cars: Vec<_> = car_query.iter_mut().collect();
+    cars.sort_by_key(|(_, p, ..)| p.handle);
+
+    for (t, _, car, boost) in cars.iter_mut() {
+        let (pos, car) = (t.translation.xy(), **car);
+        if pad_query
+            .iter()
+            .any(|pad| touches(pos, car, pad.translation.xy(), PAD_SIZE))
+        {
+            boost.refill(PAD_BOOST_FRAMES);
+        }
+        for (pickup_t, mut pickup) in pickup_query.iter_mut() {
+            if pickup.is_available() && touches(pos, car, pickup_t.translation.xy(), PICKUP_SIZE) {
+                pickup.respawn_frames = PICKUP_RESPAWN_FRAMES;
+                boost.refill(PICKUP_BOOST_FRAMES);
+            }
+        }
+    }
+}
+
+pub fn update_pickup_visibility(mut query: Query<(&Pickup, &mut Visibility)>) {
+    for (pickup, mut visibility) in query.iter_mut() {
+        visibility.set_if_neq(if pickup.is_available() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+}
@@ -0,0 +1,256 @@
+//! Round cameras.
+//!
+//! Following gives every local player a camera on their own car, splitting the
+//! window between them in local rounds. Zooming frames all cars with a single
+//! camera, the arena view shows the whole map. C switches between them mid
+//! round. The UI is drawn by a camera of its own on top of the others, so it
+//! covers the whole window whatever the mode.
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    math::Vec3Swizzles,
+    prelude::*,
+    render::{camera::Viewport, view::RenderLayers},
+};
+use bevy_ggrs::LocalPlayers;
+
+use crate::{
+    map::Map,
+    round::{Player, RoundEntity},
+};
+
+/// World units per logical pixel while following a car.
+const FOLLOW_SCALE: f32 = 1.;
+/// Zooming in never goes closer than this.
+const MIN_ZOOM_SCALE: f32 = 0.75;
+/// Space kept around the cars when zooming, in world units.
+const ZOOM_PADDING: f32 = 150.;
+/// How fast the cameras catch up, higher is snappier.
+const CAMERA_SMOOTHING: f32 = 6.;
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    #[default]
+    Follow,
+    Zoom,
+    Arena,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 3] = [CameraMode::Follow, CameraMode::Zoom, CameraMode::Arena];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraMode::Follow => "Follow",
+            CameraMode::Zoom => "Zoom",
+            CameraMode::Arena => "Arena",
+        }
+    }
+
+    /// Accepts the names above, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundCamera {
+    /// On the car of a local player, in one of `slots` parts of the window.
+    Follow {
+        handle: usize,
+        slot: usize,
+        slots: usize,
+    },
+    /// Frames the cars or the whole arena, depending on the mode.
+    Frame,
+    /// Draws nothing but the UI.
+    Overlay,
+}
+
+pub fn cycle_camera_mode(keys: Res<Input<KeyCode>>, mut mode: ResMut<CameraMode>) {
+    if keys.just_pressed(KeyCode::C) {
+        *mode = mode.next();
+        info!("camera mode: {}", mode.name());
+    }
+}
+
+/// Spawns the cameras for the current mode, once the round starts and whenever
+/// the mode changes.
+pub fn update_cameras(
+    mut commands: Commands,
+    mode: Res<CameraMode>,
+    local_players: Res<LocalPlayers>,
+    query: Query<Entity, With<RoundCamera>>,
+) {
+    if !mode.is_changed() && !query.is_empty() {
+        return;
+    }
+    for camera in query.iter() {
+        commands.entity(camera).despawn();
+    }
+
+    let world_cameras = match *mode {
+        // there is nobody to follow when only watching
+        CameraMode::Follow if !local_players.0.is_empty() => {
+            let slots = local_players.0.len();
+            local_players
+                .0
+                .iter()
+                .enumerate()
+                .map(|(slot, handle)| RoundCamera::Follow {
+                    handle: *handle,
+                    slot,
+                    slots,
+                })
+                .collect()
+        }
+        _ => vec![RoundCamera::Frame],
+    };
+    let num_world_cameras = world_cameras.len();
+
+    for (order, round_camera) in world_cameras.into_iter().enumerate() {
+        // only the first one clears the window, the others would wipe its part
+        let clear_color = if order == 0 {
+            ClearColorConfig::Default
+        } else {
+            ClearColorConfig::None
+        };
+        commands
+            .spawn(Camera2dBundle {
+                camera: Camera {
+                    order: order as isize,
+                    ..Default::default()
+                },
+                camera_2d: Camera2d { clear_color },
+                ..Default::default()
+            })
+            .insert(UiCameraConfig { show_ui: false })
+            .insert(round_camera)
+            .insert(RoundEntity);
+    }
+
+    commands
+        .spawn(Camera2dBundle {
+            camera: Camera {
+                order: num_world_cameras as isize,
+                ..Default::default()
+            },
+            camera_2d: Camera2d {
+                clear_color: ClearColorConfig::None,
+            },
+            ..Default::default()
+        })
+        .insert(RenderLayers::none())
+        .insert(RoundCamera::Overlay)
+        .insert(RoundEntity);
+}
+
+/// Splits the window between the following cameras, in columns for two
+/// players and a grid for more. Keeps up with the window being resized.
+pub fn update_viewports(mut query: Query<(&RoundCamera, &mut Camera)>) {
+    for (round_camera, mut camera) in query.iter_mut() {
+        let RoundCamera::Follow { slot, slots, .. } = *round_camera else {
+            continue;
+        };
+        let Some(size) = camera.physical_target_size() else {
+            continue;
+        };
+        if slots <= 1 {
+            if camera.viewport.is_some() {
+                camera.viewport = None;
+            }
+            continue;
+        }
+        let grid = UVec2::new(2, if slots > 2 { 2 } else { 1 });
+        let cell = size / grid;
+        let position = UVec2::new(slot as u32 % grid.x, slot as u32 / grid.x) * cell;
+        let current = camera
+            .viewport
+            .as_ref()
+            .map(|v| (v.physical_position, v.physical_size));
+        if current != Some((position, cell)) {
+            camera.viewport = Some(Viewport {
+                physical_position: position,
+                physical_size: cell,
+                ..Default::default()
+            });
+        }
+    }
+}
+
+/// How far out a camera with a view of `view` logical pixels has to be to fit
+/// `size` world units.
+fn fit_scale(size: Vec2, view: Vec2) -> f32 {
+    (size / view).max_element()
+}
+
+/// Moves and zooms the cameras towards what they should show, easing in so
+/// rollbacks and bounces do not shake the picture.
+pub fn move_cameras(
+    time: Res<Time>,
+    mode: Res<CameraMode>,
+    map: Res<Map>,
+    car_query: Query<(&Transform, &Player), Without<RoundCamera>>,
+    mut query: Query<(
+        Ref<RoundCamera>,
+        &Camera,
+        &mut Transform,
+        &mut OrthographicProjection,
+    )>,
+) {
+    let arena = map.size();
+    let ease = 1. - (-CAMERA_SMOOTHING * time.delta_seconds()).exp();
+
+    for (round_camera, camera, mut t, mut projection) in query.iter_mut() {
+        let Some(view) = camera
+            .logical_viewport_size()
+            .filter(|v| v.min_element() > 0.)
+        else {
+            continue;
+        };
+        let arena_scale = fit_scale(arena, view);
+
+        let (center, scale) = match (*round_camera, *mode) {
+            (RoundCamera::Overlay, _) => continue,
+            (RoundCamera::Follow { handle, .. }, _) => {
+                let car = car_query.iter().find(|(_, p)| p.handle == handle);
+                let center = car.map_or(Vec2::ZERO, |(car_t, _)| car_t.translation.xy());
+                (center, FOLLOW_SCALE)
+            }
+            (RoundCamera::Frame, CameraMode::Zoom) if !car_query.is_empty() => {
+                let (min, max) = car_query.iter().fold(
+                    (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+                    |(min, max), (car_t, _)| {
+                        let pos = car_t.translation.xy();
+                        (min.min(pos), max.max(pos))
+                    },
+                );
+                let size = max - min + Vec2::splat(ZOOM_PADDING * 2.);
+                let scale =
+                    fit_scale(size, view).clamp(MIN_ZOOM_SCALE, arena_scale.max(MIN_ZOOM_SCALE));
+                ((min + max) * 0.5, scale)
+            }
+            (RoundCamera::Frame, _) => (Vec2::ZERO, arena_scale),
+        };
+
+        // no need to show much beyond the walls
+        let room = (arena - view * scale).max(Vec2::ZERO) * 0.5;
+        let center = center.clamp(-room, room);
+
+        if round_camera.is_added() {
+            t.translation = center.extend(t.translation.z);
+            projection.scale = scale;
+        } else {
+            let pos = t.translation.xy().lerp(center, ease);
+            t.translation = pos.extend(t.translation.z);
+            projection.scale += (scale - projection.scale) * ease;
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! Car classes, picked per player before the round.
+//!
+//! The size of a class is fixed here, how it drives on top of the
+//! [`CarTuning`](crate::tuning::CarTuning) comes from the tuning file, see
+//! [`ClassTuning`].
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{round::PLAYER_SIZE, MAX_PLAYERS};
+
+#[derive(
+    Component,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum CarClass {
+    /// Quick and nimble, but pushed around easily.
+    Light,
+    #[default]
+    Standard,
+    /// Slow, grips the road and shoves the others aside.
+    Heavy,
+}
+
+impl CarClass {
+    pub const ALL: [CarClass; 3] = [CarClass::Light, CarClass::Standard, CarClass::Heavy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CarClass::Light => "Light",
+            CarClass::Standard => "Standard",
+            CarClass::Heavy => "Heavy",
+        }
+    }
+
+    /// Accepts the name shown in the menus, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|class| class.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL
+            .iter()
+            .position(|class| class == self)
+            .unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// From the back to the front bumper, the car is half as wide.
+    pub fn length(&self) -> f32 {
+        let scale = match self {
+            CarClass::Light => 0.85,
+            CarClass::Standard => 1.,
+            CarClass::Heavy => 1.2,
+        };
+        PLAYER_SIZE * scale
+    }
+
+    /// The car collides as a circle of this radius.
+    pub fn radius(&self) -> f32 {
+        self.length() * 0.35
+    }
+
+    pub fn sprite_size(&self) -> Vec2 {
+        Vec2::new(self.length() * 0.5, self.length())
+    }
+}
+
+/// How a class drives compared to the base tuning.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClassTuning {
+    /// Scales the acceleration and the top speed.
+    pub speed: f32,
+    /// Scales how much of the sideways speed the tires take away.
+    pub grip: f32,
+    /// Heavier cars are pushed less when two cars collide.
+    pub mass: f32,
+}
+
+impl Default for ClassTuning {
+    fn default() -> Self {
+        Self {
+            speed: 1.,
+            grip: 1.,
+            mass: 1.,
+        }
+    }
+}
+
+impl ClassTuning {
+    /// Applies the grip to a share of sideways speed kept per frame.
+    pub fn drift(&self, drift: f32) -> f32 {
+        (1. - (1. - drift) * self.grip).clamp(0., 1.)
+    }
+}
+
+/// The class of every player's car, indexed by player handle.
+#[derive(Resource, Clone, Debug)]
+pub struct PlayerCars(pub Vec<CarClass>);
+
+impl PlayerCars {
+    pub fn local(selected: &SelectedCars, num_players: usize) -> Self {
+        Self(selected.0.iter().copied().take(num_players).collect())
+    }
+
+    pub fn get(&self, handle: usize) -> CarClass {
+        self.0.get(handle).copied().unwrap_or_default()
+    }
+}
+
+/// The classes picked in the menus for the local players. Online, only the
+/// first one is used.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelectedCars(pub [CarClass; MAX_PLAYERS]);
@@ -3,10 +3,10 @@ use bevy_ggrs::Rollback;
 
 use crate::round::{Player, Velocity};
 
-#[derive(Default, Reflect, Hash, Component)]
+#[derive(Default, Reflect, Hash, Component, Clone, Debug, PartialEq)]
 #[reflect(Hash)]
 pub struct Checksum {
-    value: u16,
+    pub value: u16,
 }
 
 pub fn checksum_players(
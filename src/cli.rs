@@ -0,0 +1,333 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{
+    camera::CameraMode,
+    car::{CarClass, SelectedCars},
+    menu::{
+        connect::{ConnectData, MatchboxAddr},
+        main::LocalRound,
+        online::{is_valid_lobby_code, sanitize_lobby_code},
+        settings::SessionSettings,
+    },
+    mode::{GameMode, SelectedMode},
+    netsim::NetworkConditions,
+    AppState, MAX_PLAYERS,
+};
+
+const USAGE: &str = "\
+Usage: bevy_ggrs_demo [OPTIONS]
+
+Modes (default: main menu):
+    --local                 start a local round right away
+    --lobby <CODE>          join the lobby with the given code
+    --quick                 join a quick match
+
+Overrides:
+    --players <N>           number of players per match (1-4, online at least 2)
+    --game-mode <MODE>      tag, combat or free-drive, online only the host's
+                            counts
+    --car <CLASS>           light, standard or heavy, for player 1
+    --camera <MODE>         follow, zoom or arena, C switches during the round
+    --input-delay <N>       input delay in frames
+    --matchbox <URL>        matchbox signaling server, e.g. ws://127.0.0.1:3536
+    --host-signaling        run the signaling server in this process, on the
+                            --matchbox address (needs the local-signaling feature)
+
+Simulated network conditions, for the GGRS traffic of this instance:
+    --latency <MS>          delay every packet by this many milliseconds
+    --jitter <MS>           add up to this many milliseconds at random
+    --packet-loss <PERCENT> drop this share of packets
+    --reorder <PERCENT>     hold back this share of packets so they arrive late
+
+    -h, --help              print this help";
+
+/// Where the game should go once all assets are loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LaunchMode {
+    Local,
+    Lobby(String),
+    QuickMatch,
+}
+
+/// Options passed on the command line (or a share link on the web),
+/// consumed when the main menu is first entered.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LaunchOptions {
+    pub mode: Option<LaunchMode>,
+    pub num_players: Option<usize>,
+    pub game_mode: Option<GameMode>,
+    pub car: Option<CarClass>,
+    pub camera: Option<CameraMode>,
+    pub input_delay: Option<usize>,
+    pub matchbox_addr: Option<String>,
+    pub host_signaling: bool,
+    pub latency_ms: Option<u64>,
+    pub jitter_ms: Option<u64>,
+    pub packet_loss: Option<u8>,
+    pub reorder: Option<u8>,
+}
+
+impl LaunchOptions {
+    /// Parses the process arguments, exiting with a usage message if they are invalid.
+    pub fn from_env() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("error: {e}\n\n{USAGE}");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Joins the lobby of the share link the page was opened with, if any.
+    #[cfg(target_arch = "wasm32")]
+    pub fn from_url() -> Self {
+        Self {
+            mode: crate::share::lobby_code_from_url()
+                .map(|code| sanitize_lobby_code(&code))
+                .filter(|code| is_valid_lobby_code(code))
+                .map(LaunchMode::Lobby),
+            ..Default::default()
+        }
+    }
+
+    /// Returns `Ok(None)` if the help was requested.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("{name} requires a value"))
+            };
+            let mode = match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--local" => Some(LaunchMode::Local),
+                "--quick" => Some(LaunchMode::QuickMatch),
+                "--lobby" => {
+                    let code = value("--lobby")?;
+                    if !is_valid_lobby_code(&sanitize_lobby_code(&code)) {
+                        return Err(format!("invalid lobby code '{code}'"));
+                    }
+                    Some(LaunchMode::Lobby(code))
+                }
+                "--players" => {
+                    options.num_players = Some(parse_number("--players", value("--players")?)?);
+                    None
+                }
+                "--game-mode" => {
+                    let name = value("--game-mode")?;
+                    let mode = GameMode::from_name(&name)
+                        .ok_or_else(|| format!("unknown game mode '{name}'"))?;
+                    options.game_mode = Some(mode);
+                    None
+                }
+                "--car" => {
+                    let name = value("--car")?;
+                    let car = CarClass::from_name(&name)
+                        .ok_or_else(|| format!("unknown car class '{name}'"))?;
+                    options.car = Some(car);
+                    None
+                }
+                "--camera" => {
+                    let name = value("--camera")?;
+                    let camera = CameraMode::from_name(&name)
+                        .ok_or_else(|| format!("unknown camera mode '{name}'"))?;
+                    options.camera = Some(camera);
+                    None
+                }
+                "--input-delay" => {
+                    let delay = parse_number("--input-delay", value("--input-delay")?)?;
+                    options.input_delay = Some(delay);
+                    None
+                }
+                "--matchbox" => {
+                    options.matchbox_addr = Some(value("--matchbox")?);
+                    None
+                }
+                "--host-signaling" => {
+                    if !cfg!(all(
+                        feature = "local-signaling",
+                        not(target_arch = "wasm32")
+                    )) {
+                        return Err("--host-signaling needs the local-signaling feature".to_owned());
+                    }
+                    options.host_signaling = true;
+                    None
+                }
+                "--latency" => {
+                    options.latency_ms = Some(parse_number("--latency", value("--latency")?)?);
+                    None
+                }
+                "--jitter" => {
+                    options.jitter_ms = Some(parse_number("--jitter", value("--jitter")?)?);
+                    None
+                }
+                "--packet-loss" => {
+                    let loss = parse_percent("--packet-loss", value("--packet-loss")?)?;
+                    options.packet_loss = Some(loss);
+                    None
+                }
+                "--reorder" => {
+                    options.reorder = Some(parse_percent("--reorder", value("--reorder")?)?);
+                    None
+                }
+                _ => return Err(format!("unknown argument '{arg}'")),
+            };
+            if let Some(mode) = mode {
+                if options.mode.is_some() {
+                    return Err("only one of --local, --lobby and --quick can be given".to_owned());
+                }
+                options.mode = Some(mode);
+            }
+        }
+
+        #[cfg(all(feature = "local-signaling", not(target_arch = "wasm32")))]
+        if options.host_signaling {
+            crate::signaling::bind_addr(options.signaling_url())?;
+        }
+
+        if let Some(num_players) = options.num_players {
+            let min_players = match options.mode {
+                Some(LaunchMode::Local) => 1,
+                _ => 2,
+            };
+            if !(min_players..=MAX_PLAYERS).contains(&num_players) {
+                return Err(format!(
+                    "--players must be between {min_players} and {MAX_PLAYERS}"
+                ));
+            }
+        }
+
+        Ok(Some(options))
+    }
+
+    /// Applies the setting overrides, the launch mode is handled by [`launch`].
+    pub fn apply(&self, app: &mut App) {
+        let mut settings = SessionSettings::default();
+        if let Some(num_players) = self.num_players {
+            settings.num_players = num_players;
+        }
+        if let Some(input_delay) = self.input_delay {
+            settings.input_delay = input_delay;
+        }
+        if !settings.is_valid() {
+            eprintln!("error: invalid session settings {settings:?}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+        app.insert_resource(settings);
+        if let Some(mode) = self.game_mode {
+            app.insert_resource(SelectedMode(mode));
+        }
+        if let Some(car) = self.car {
+            let mut cars = SelectedCars::default();
+            cars.0[0] = car;
+            app.insert_resource(cars);
+        }
+        if let Some(camera) = self.camera {
+            app.insert_resource(camera);
+        }
+
+        if let Some(addr) = &self.matchbox_addr {
+            app.insert_resource(MatchboxAddr(addr.clone()));
+        }
+
+        let conditions = self.network_conditions();
+        if !conditions.is_perfect() {
+            warn!("simulating network conditions: {conditions:?}");
+        }
+        app.insert_resource(conditions);
+
+        #[cfg(all(feature = "local-signaling", not(target_arch = "wasm32")))]
+        if self.host_signaling {
+            self.host_signaling_server();
+        }
+    }
+
+    pub fn network_conditions(&self) -> NetworkConditions {
+        let percent = |p: Option<u8>| f32::from(p.unwrap_or_default()) / 100.;
+        NetworkConditions {
+            latency: Duration::from_millis(self.latency_ms.unwrap_or_default()),
+            jitter: Duration::from_millis(self.jitter_ms.unwrap_or_default()),
+            loss: percent(self.packet_loss),
+            reorder: percent(self.reorder),
+        }
+    }
+
+    #[cfg(all(feature = "local-signaling", not(target_arch = "wasm32")))]
+    fn signaling_url(&self) -> &str {
+        self.matchbox_addr
+            .as_deref()
+            .unwrap_or(crate::menu::connect::MATCHBOX_ADDR)
+    }
+
+    #[cfg(all(feature = "local-signaling", not(target_arch = "wasm32")))]
+    fn host_signaling_server(&self) {
+        let host = match crate::signaling::bind_addr(self.signaling_url()) {
+            Ok(host) => host,
+            Err(e) => {
+                eprintln!("error: {e}\n\n{USAGE}");
+                std::process::exit(2);
+            }
+        };
+        match crate::signaling::spawn(&host) {
+            Ok(_) => {}
+            // another instance on this machine got there first, just use that one
+            Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => {
+                info!("signaling server already running on {host}");
+            }
+            Err(e) => {
+                eprintln!("error: could not host signaling server on {host}: {e}");
+                std::process::exit(2);
+            }
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{name} expects a number, got '{value}'"))
+}
+
+fn parse_percent(name: &str, value: String) -> Result<u8, String> {
+    let percent = parse_number(name, value)?;
+    if percent > 100 {
+        return Err(format!("{name} must be between 0 and 100"));
+    }
+    Ok(percent)
+}
+
+/// Jumps straight into the requested mode the first time the main menu is entered.
+pub fn launch(
+    mut commands: Commands,
+    mut state: ResMut<NextState<AppState>>,
+    options: Option<ResMut<LaunchOptions>>,
+    local_round: LocalRound,
+    selected_mode: Res<SelectedMode>,
+) {
+    let Some(mode) = options.and_then(|mut o| o.mode.take()) else {
+        return;
+    };
+
+    match mode {
+        LaunchMode::Local => {
+            local_round.start(&mut commands, selected_mode.0);
+            state.set(AppState::RoundLocal);
+        }
+        LaunchMode::Lobby(code) => {
+            commands.insert_resource(ConnectData::lobby(&code));
+            state.set(AppState::MenuConnect);
+        }
+        LaunchMode::QuickMatch => {
+            commands.insert_resource(ConnectData::quick_match(local_round.settings.num_players));
+            state.set(AppState::MenuConnect);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Combat: cars shoot at each other until only one is left driving.
+//!
+//! Projectiles are rollback entities, spawned and despawned in the
+//! [`GgrsSchedule`], so a misprediction can take back or bring back shots.
+//! Their visuals are added separately, rollback only restores the components
+//! registered for it.
+
+use std::hash::{Hash, Hasher};
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_ggrs::{AddRollbackCommandExtension, GgrsApp, GgrsSchedule, Rollback, RollbackOrdered};
+
+use crate::{
+    car::CarClass,
+    desync::DumpApp,
+    map::Map,
+    mode::{in_mode, in_round, GameMode, ModeSystems},
+    round::{
+        spawn_players, CarControls, FrameCount, Player, PlayerNames, RoundEnd, RoundEntity,
+        RoundUI, Velocity,
+    },
+    AppState, FontAssets, BUTTON_TEXT,
+};
+
+pub const MAX_HEALTH: u32 = 5;
+const FIRE_COOLDOWN_FRAMES: u32 = 20;
+const PROJECTILE_SPEED: f32 = 12.;
+const PROJECTILE_FRAMES: u32 = 90;
+const PROJECTILE_RADIUS: f32 = 5.;
+const PROJECTILE_COLOR: Color = Color::rgb(1., 0.95, 0.6);
+/// Alpha of a wrecked car.
+const WRECK_ALPHA: f32 = 0.3;
+
+/// Hits a car can take before it is wrecked.
+#[derive(Reflect, Hash, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Health {
+    pub hp: u32,
+    /// The frame the last hit landed on, once the car is wrecked.
+    pub wrecked_on: Option<u32>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            hp: MAX_HEALTH,
+            wrecked_on: None,
+        }
+    }
+}
+
+impl Health {
+    /// Wrecked cars cannot drive, shoot or be shot anymore.
+    pub fn is_wrecked(&self) -> bool {
+        self.hp == 0
+    }
+
+    fn hit(&mut self, frame: u32) {
+        self.hp = self.hp.saturating_sub(1);
+        if self.is_wrecked() {
+            self.wrecked_on = Some(frame);
+        }
+    }
+}
+
+#[derive(Default, Reflect, Hash, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Gun {
+    /// Frames until the next shot.
+    pub cooldown: u32,
+}
+
+#[derive(Default, Reflect, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Projectile {
+    /// Handle of the player who fired it, they cannot hit themselves.
+    pub owner: usize,
+    /// Kept here as well as in the transform, so it is part of the checksum.
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub frames_left: u32,
+}
+
+impl Hash for Projectile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.owner.hash(state);
+        for value in [self.position, self.velocity] {
+            value.x.to_bits().hash(state);
+            value.y.to_bits().hash(state);
+        }
+        self.frames_left.hash(state);
+    }
+}
+
+#[derive(Component)]
+pub struct CombatHud;
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        let combat = in_mode(GameMode::Combat);
+        app.rollback_component_with_reflect::<Health>()
+            .rollback_component_with_reflect::<Gun>()
+            .rollback_component_with_reflect::<Projectile>()
+            .checksum_component_with_hash::<Health>()
+            .checksum_component_with_hash::<Gun>()
+            .checksum_component_with_hash::<Projectile>()
+            .dump_component::<Health>()
+            .dump_component::<Gun>()
+            .dump_component::<Projectile>()
+            .add_systems(
+                GgrsSchedule,
+                update_combat.in_set(ModeSystems).run_if(combat.clone()),
+            )
+            .add_systems(
+                Update,
+                (
+                    add_projectile_visuals,
+                    update_wreck_visuals,
+                    update_combat_hud,
+                    check_combat_win,
+                )
+                    .run_if(in_round())
+                    .run_if(combat.clone()),
+            );
+        for state in [AppState::RoundLocal, AppState::RoundOnline] {
+            app.add_systems(
+                OnEnter(state),
+                (
+                    // the cars have to be spawned for real first
+                    (apply_deferred, add_weapons).chain().after(spawn_players),
+                    setup_combat_hud,
+                )
+                    .run_if(combat.clone()),
+            );
+        }
+    }
+}
+
+/// Arms the cars spawned by [`spawn_players`].
+pub fn add_weapons(mut commands: Commands, query: Query<Entity, With<Player>>) {
+    for car in query.iter() {
+        commands
+            .entity(car)
+            .insert(Health::default())
+            .insert(Gun::default());
+    }
+}
+
+/// With at least two cars in the round, it is over once one or none is left.
+fn is_decided<'a>(healths: impl Iterator<Item = &'a Health>) -> bool {
+    let (cars, left) = healths.fold((0, 0), |(cars, left), health| {
+        (cars + 1, left + usize::from(!health.is_wrecked()))
+    });
+    cars > 1 && left <= 1
+}
+
+/// Moves the projectiles, lands their hits and fires new ones.
+pub fn update_combat(
+    mut commands: Commands,
+    mut car_query: Query<
+        (
+            &Transform,
+            &Velocity,
+            &CarControls,
+            &Player,
+            &CarClass,
+            &mut Gun,
+            &mut Health,
+        ),
+        With<Rollback>,
+    >,
+    mut projectile_query: Query<
+        (Entity, &Rollback, &mut Transform, &mut Projectile),
+        Without<Player>,
+    >,
+    order: Res<RollbackOrdered>,
+    frame: Res<FrameCount>,
+    map: Res<Map>,
+) {
+    // once the round is decided nothing changes anymore, so the result stays put
+    if is_decided(car_query.iter().map(|(.., health)| health)) {
+        return;
+    }
+
+    // query order is not the same on every peer, the lower handle gets hit first
+    let mut cars: Vec<_> = car_query.iter_mut().collect();
+    cars.sort_by_key(|(_, _, _, p, ..)| p.handle);
+    // and older projectiles hit first
+    let mut projectiles: Vec<_> = projectile_query.iter_mut().collect();
+    projectiles.sort_by_key(|(_, rollback, _, _)| order.order(**rollback));
+
+    let bounds = map.size() * 0.5;
+    for (entity, _, t, projectile) in projectiles.iter_mut() {
+        let pos = projectile.position + projectile.velocity;
+        projectile.position = pos;
+        t.translation.x = pos.x;
+        t.translation.y = pos.y;
+        projectile.frames_left = projectile.frames_left.saturating_sub(1);
+
+        let target = cars.iter_mut().find(|(car_t, _, _, p, car, _, health)| {
+            p.handle != projectile.owner
+                && !health.is_wrecked()
+                && car_t.translation.xy().distance(pos) < car.radius() + PROJECTILE_RADIUS
+        });
+        let hit = target.is_some();
+        if let Some((.., health)) = target {
+            health.hit(frame.frame);
+        }
+
+        let blocked = pos.abs().cmpgt(bounds).any()
+            || map.obstacles.iter().any(|obstacle| obstacle.contains(pos));
+        if hit || blocked || projectile.frames_left == 0 {
+            commands.entity(*entity).despawn();
+        }
+    }
+
+    for (t, v, controls, p, car, gun, health) in cars.iter_mut() {
+        gun.cooldown = gun.cooldown.saturating_sub(1);
+        if health.is_wrecked() || !controls.fire || gun.cooldown > 0 {
+            continue;
+        }
+        gun.cooldown = FIRE_COOLDOWN_FRAMES;
+
+        let up = t.up().xy();
+        let pos = t.translation.xy() + up * car.length() * 0.6;
+        commands
+            .spawn(Transform::from_translation(pos.extend(0.4)))
+            .insert(Projectile {
+                owner: p.handle,
+                position: pos,
+                velocity: up * PROJECTILE_SPEED + v.0,
+                frames_left: PROJECTILE_FRAMES,
+            })
+            .add_rollback();
+    }
+}
+
+/// The last car left wins, if the last ones were wrecked on the same frame it
+/// is a draw. Ends the round once the frame that decided it is confirmed.
+pub fn check_combat_win(query: Query<(&Player, &Health)>, mut round_end: RoundEnd) {
+    if !is_decided(query.iter().map(|(_, health)| health)) {
+        return;
+    }
+    let decided_on = query
+        .iter()
+        .filter_map(|(_, health)| health.wrecked_on)
+        .max()
+        .unwrap_or_default();
+    if !round_end.is_confirmed(decided_on) {
+        return;
+    }
+    let winner = query
+        .iter()
+        .find(|(_, health)| !health.is_wrecked())
+        .map(|(p, _)| p.handle);
+    round_end.declare(winner);
+}
+
+/// Projectiles spawned or brought back by a rollback only have their rolled
+/// back components, this makes them visible and part of the round.
+pub fn add_projectile_visuals(mut commands: Commands, query: Query<Entity, Added<Projectile>>) {
+    for entity in query.iter() {
+        commands.entity(entity).insert((
+            Sprite {
+                color: PROJECTILE_COLOR,
+                custom_size: Some(Vec2::splat(PROJECTILE_RADIUS * 2.)),
+                ..Default::default()
+            },
+            Handle::<Image>::default(),
+            GlobalTransform::default(),
+            VisibilityBundle::default(),
+            RoundEntity,
+        ));
+    }
+}
+
+pub fn update_wreck_visuals(mut query: Query<(&Health, &mut Sprite), Changed<Health>>) {
+    for (health, mut sprite) in query.iter_mut() {
+        let alpha = if health.is_wrecked() { WRECK_ALPHA } else { 1. };
+        sprite.color.set_a(alpha);
+    }
+}
+
+pub fn setup_combat_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 32.0,
+                    color: BUTTON_TEXT,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..Default::default()
+            }),
+        )
+        .insert(CombatHud)
+        .insert(RoundUI);
+}
+
+pub fn update_combat_hud(
+    names: Res<PlayerNames>,
+    query: Query<(&Player, &Health)>,
+    mut hud_query: Query<&mut Text, With<CombatHud>>,
+) {
+    let mut cars: Vec<_> = query.iter().collect();
+    cars.sort_by_key(|(p, _)| p.handle);
+    let hud = cars
+        .iter()
+        .map(|(p, health)| {
+            let name = names.get(p.handle);
+            if health.is_wrecked() {
+                format!("{name}: wrecked")
+            } else {
+                format!("{name}: {} HP", health.hp)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = hud.clone();
+    }
+}
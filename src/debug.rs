@@ -0,0 +1,248 @@
+//! Debug overlay showing when and how far GGRS rolls back.
+//!
+//! F1 toggles the rollback statistics, F2 the ghosts of the cars at their last
+//! confirmed position.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    time::Duration,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::Instant};
+use bevy_ggrs::{ConfirmedFrameCount, RollbackFrameCount, Session};
+
+use crate::{
+    car::PlayerCars,
+    round::{Player, PlayerColors, RoundEntity, RoundUI},
+    FontAssets, GGRSConfig, BUTTON_TEXT,
+};
+
+/// Rollbacks deeper than this share the last histogram bucket.
+const MAX_DEPTH: usize = 12;
+const GHOST_ALPHA: f32 = 0.35;
+/// Frames kept for the ghosts, in case nothing gets confirmed for a while.
+const HISTORY_LEN: i32 = 128;
+
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub stats: bool,
+    pub ghosts: bool,
+}
+
+#[derive(Resource, Default)]
+pub struct RollbackStats {
+    /// Furthest frame simulated so far, rollbacks go back from here.
+    latest_frame: i32,
+    recent: VecDeque<Instant>,
+    total: usize,
+    depths: [usize; MAX_DEPTH + 1],
+}
+
+impl RollbackStats {
+    fn per_second(&self) -> usize {
+        self.recent.len()
+    }
+}
+
+/// Car transforms of recently simulated frames, kept until they are confirmed.
+#[derive(Resource, Default)]
+pub struct FrameHistory(BTreeMap<i32, Vec<(usize, Transform)>>);
+
+/// The last frame that will not be simulated again.
+///
+/// bevy_ggrs only updates the [`ConfirmedFrameCount`] of a SyncTest session
+/// during its first few frames. It resimulates everything after the frame
+/// `check_distance` frames back, so that one is as good as confirmed.
+#[derive(SystemParam)]
+pub struct ConfirmedFrame<'w> {
+    confirmed: Res<'w, ConfirmedFrameCount>,
+    frame: Res<'w, RollbackFrameCount>,
+    session: Option<Res<'w, Session<GGRSConfig>>>,
+}
+
+impl ConfirmedFrame<'_> {
+    pub fn get(&self) -> i32 {
+        match self.session.as_deref() {
+            Some(Session::SyncTest(s)) => i32::from(*self.frame) - s.check_distance() as i32,
+            _ => (*self.confirmed).into(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct DebugStatsText;
+
+#[derive(Component)]
+pub struct Ghost {
+    pub handle: usize,
+}
+
+pub fn reset_rollback_stats(mut commands: Commands) {
+    commands.insert_resource(RollbackStats::default());
+    commands.insert_resource(FrameHistory::default());
+}
+
+/// Runs in [`bevy_ggrs::LoadWorld`], after the frame count was set to the frame being restored.
+pub fn count_rollback(frame: Res<RollbackFrameCount>, mut stats: ResMut<RollbackStats>) {
+    let frame: i32 = (*frame).into();
+    let depth = (stats.latest_frame - frame).max(0) as usize;
+    stats.depths[depth.min(MAX_DEPTH)] += 1;
+    stats.total += 1;
+    stats.recent.push_back(Instant::now());
+}
+
+/// Runs at the end of the [`bevy_ggrs::GgrsSchedule`].
+pub fn record_frame(
+    frame: Res<RollbackFrameCount>,
+    confirmed: ConfirmedFrame,
+    query: Query<(&Transform, &Player)>,
+    mut stats: ResMut<RollbackStats>,
+    mut history: ResMut<FrameHistory>,
+) {
+    let frame: i32 = (*frame).into();
+    let confirmed = confirmed.get();
+    stats.latest_frame = stats.latest_frame.max(frame);
+
+    let transforms = query.iter().map(|(t, p)| (p.handle, *t)).collect();
+    history.0.insert(frame, transforms);
+    // keep the confirmed frame itself for the ghosts
+    history
+        .0
+        .retain(|f, _| *f >= confirmed && *f > stats.latest_frame - HISTORY_LEN);
+}
+
+pub fn toggle_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(KeyCode::F1) {
+        overlay.stats = !overlay.stats;
+    }
+    if keys.just_pressed(KeyCode::F2) {
+        overlay.ghosts = !overlay.ghosts;
+    }
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(16.),
+                top: Val::Px(16.),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 20.,
+                    color: BUTTON_TEXT,
+                },
+            ),
+            ..Default::default()
+        })
+        .insert(DebugStatsText)
+        .insert(RoundUI);
+}
+
+pub fn update_stats_display(
+    overlay: Res<DebugOverlay>,
+    confirmed: ConfirmedFrame,
+    mut stats: ResMut<RollbackStats>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugStatsText>>,
+) {
+    let now = Instant::now();
+    while stats
+        .recent
+        .front()
+        .is_some_and(|t| now.duration_since(*t) > Duration::from_secs(1))
+    {
+        stats.recent.pop_front();
+    }
+
+    for (mut text, mut visibility) in query.iter_mut() {
+        if !overlay.stats {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        *visibility = Visibility::Inherited;
+
+        let confirmed = confirmed.get();
+        let mut value = format!(
+            "frame {} (confirmed {confirmed})\nrollbacks/s {}  total {}\ndepth:",
+            stats.latest_frame,
+            stats.per_second(),
+            stats.total,
+        );
+        let most = stats
+            .depths
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        for (depth, count) in stats.depths.iter().enumerate().filter(|(_, c)| **c > 0) {
+            let bar = "#".repeat((count * 20).div_ceil(most));
+            let plus = if depth == MAX_DEPTH { "+" } else { " " };
+            write!(value, "\n{depth:>2}{plus} {bar} {count}").unwrap();
+        }
+        text.sections[0].value = value;
+    }
+}
+
+/// Shows where each car was on the last confirmed frame, the cars themselves
+/// are drawn at their predicted position.
+pub fn update_ghosts(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    confirmed: ConfirmedFrame,
+    history: Res<FrameHistory>,
+    colors: Res<PlayerColors>,
+    cars: Res<PlayerCars>,
+    mut ghost_query: Query<(Entity, &Ghost, &mut Transform, &mut Visibility)>,
+) {
+    let Some(transforms) = history.0.get(&confirmed.get()) else {
+        return;
+    };
+
+    let visibility = if overlay.ghosts {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (handle, transform) in transforms.iter() {
+        let mut transform = *transform;
+        // above the arena, below the cars
+        transform.translation.z = 0.5;
+
+        let ghost = ghost_query
+            .iter_mut()
+            .find(|(_, g, ..)| g.handle == *handle);
+        match ghost {
+            Some((_, _, mut ghost_transform, mut ghost_visibility)) => {
+                *ghost_transform = transform;
+                *ghost_visibility = visibility;
+            }
+            None => {
+                commands
+                    .spawn(SpriteBundle {
+                        transform,
+                        sprite: Sprite {
+                            color: colors.get(*handle).with_a(GHOST_ALPHA),
+                            custom_size: Some(cars.get(*handle).sprite_size()),
+                            ..Default::default()
+                        },
+                        visibility,
+                        ..Default::default()
+                    })
+                    .insert(Ghost { handle: *handle })
+                    .insert(RoundEntity);
+            }
+        }
+    }
+    // ghosts of the despawned are of no use
+    for (entity, ghost, ..) in ghost_query.iter() {
+        if !transforms.iter().any(|(handle, _)| *handle == ghost.handle) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Dumps of the rolled back state, written when peers report a desync.
+//!
+//! Every frame GGRS exchanges checksums for is snapshotted. Once a peer reports
+//! a different checksum for one of them, the snapshot is written to disk so the
+//! dumps of both peers can be compared with the `desync_diff` tool.
+
+use std::collections::BTreeMap;
+
+use bevy::{
+    ecs::world::EntityRef,
+    prelude::*,
+    reflect::{serde::TypedReflectSerializer, GetTypeRegistration, TypeRegistry},
+};
+use bevy_ggrs::{LocalPlayers, Rollback, RollbackFrameCount, RollbackOrdered};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::DESYNC_INTERVAL;
+
+/// Snapshots kept around for peers whose checksums arrive late.
+const KEPT_SNAPSHOTS: usize = 32;
+#[cfg(not(target_arch = "wasm32"))]
+const DUMP_DIR: &str = "desync";
+
+type ReadComponent = for<'w> fn(EntityRef<'w>) -> Option<&'w dyn Reflect>;
+type ReadResource = fn(&World, &TypeRegistry) -> Option<Value>;
+
+/// The components and resources that end up in a dump, by name.
+#[derive(Resource, Default)]
+pub struct DumpedTypes {
+    components: Vec<(&'static str, ReadComponent)>,
+    resources: Vec<(&'static str, ReadResource)>,
+}
+
+fn read_component<T: Component + Reflect>(entity: EntityRef<'_>) -> Option<&dyn Reflect> {
+    entity.get::<T>().map(|c| c as &dyn Reflect)
+}
+
+fn read_resource<T: Resource + Reflect>(world: &World, registry: &TypeRegistry) -> Option<Value> {
+    world.get_resource::<T>().map(|r| to_value(r, registry))
+}
+
+fn read_serde_resource<T: Resource + Serialize>(world: &World, _: &TypeRegistry) -> Option<Value> {
+    world.get_resource::<T>().map(|r| {
+        serde_json::to_value(r).unwrap_or_else(|e| Value::String(format!("not serializable: {e}")))
+    })
+}
+
+/// Adds types to the desync dumps, next to their rollback registration.
+pub trait DumpApp {
+    fn dump_component<T: Component + Reflect + TypePath + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self;
+    fn dump_resource<T: Resource + Reflect + TypePath + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self;
+    /// For resources that cannot be reflected, but serialized.
+    fn dump_resource_with_serde<T: Resource + Serialize + TypePath>(&mut self) -> &mut Self;
+}
+
+impl DumpApp for App {
+    fn dump_component<T: Component + Reflect + TypePath + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self {
+        self.register_type::<T>();
+        self.world
+            .get_resource_or_insert_with(DumpedTypes::default)
+            .components
+            .push((T::short_type_path(), read_component::<T>));
+        self
+    }
+
+    fn dump_resource<T: Resource + Reflect + TypePath + GetTypeRegistration>(
+        &mut self,
+    ) -> &mut Self {
+        self.register_type::<T>();
+        self.world
+            .get_resource_or_insert_with(DumpedTypes::default)
+            .resources
+            .push((T::short_type_path(), read_resource::<T>));
+        self
+    }
+
+    fn dump_resource_with_serde<T: Resource + Serialize + TypePath>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(DumpedTypes::default)
+            .resources
+            .push((T::short_type_path(), read_serde_resource::<T>));
+        self
+    }
+}
+
+/// The dumped state of one frame.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameDump {
+    pub frame: i32,
+    pub resources: Map<String, Value>,
+    pub entities: Vec<EntityDump>,
+}
+
+/// A rollback entity, identified by the order it was spawned in, which is the
+/// same for every peer. Components keep the order they were registered in.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityDump {
+    pub rollback: usize,
+    pub components: Map<String, Value>,
+}
+
+/// What gets written to disk, the checksums are the ones GGRS compared.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DesyncDump {
+    pub local_checksum: String,
+    pub remote_checksum: String,
+    pub state: FrameDump,
+}
+
+/// Sent for every [`bevy_ggrs::ggrs::GgrsEvent::DesyncDetected`].
+#[derive(Event, Clone, Copy, Debug)]
+pub struct Desync {
+    pub frame: i32,
+    pub local_checksum: u128,
+    pub remote_checksum: u128,
+}
+
+/// Recent snapshots of the frames GGRS checks for desyncs.
+#[derive(Resource, Default)]
+pub struct FrameSnapshots(pub BTreeMap<i32, FrameDump>);
+
+fn to_value(value: &dyn Reflect, registry: &TypeRegistry) -> Value {
+    serde_json::to_value(TypedReflectSerializer::new(value, registry))
+        .unwrap_or_else(|e| Value::String(format!("not serializable: {e}")))
+}
+
+/// Runs at the end of the [`bevy_ggrs::GgrsSchedule`], after the checksums.
+pub fn snapshot_frame(world: &mut World) {
+    let frame: i32 = (*world.resource::<RollbackFrameCount>()).into();
+    if frame % DESYNC_INTERVAL as i32 != 0 {
+        return;
+    }
+
+    let mut rollbacks: Vec<_> = world
+        .query::<(Entity, &Rollback)>()
+        .iter(world)
+        .map(|(entity, rollback)| (world.resource::<RollbackOrdered>().order(*rollback), entity))
+        .collect();
+    rollbacks.sort();
+
+    let registry = world.resource::<AppTypeRegistry>().read();
+    let types = world.resource::<DumpedTypes>();
+    let mut dump = FrameDump {
+        frame,
+        ..Default::default()
+    };
+    for (name, read) in types.resources.iter() {
+        if let Some(resource) = read(world, &registry) {
+            dump.resources.insert(name.to_string(), resource);
+        }
+    }
+    for (rollback, entity) in rollbacks {
+        let entity = world.entity(entity);
+        let components = types
+            .components
+            .iter()
+            .filter_map(|(name, read)| Some((name.to_string(), to_value(read(entity)?, &registry))))
+            .collect();
+        dump.entities.push(EntityDump {
+            rollback,
+            components,
+        });
+    }
+    drop(registry);
+
+    let mut snapshots = world.resource_mut::<FrameSnapshots>();
+    snapshots.0.insert(frame, dump);
+    while snapshots.0.len() > KEPT_SNAPSHOTS {
+        snapshots.0.pop_first();
+    }
+}
+
+pub fn reset_snapshots(mut commands: Commands) {
+    commands.insert_resource(FrameSnapshots::default());
+}
+
+pub fn dump_desyncs(
+    mut desyncs: EventReader<Desync>,
+    snapshots: Res<FrameSnapshots>,
+    local_players: Res<LocalPlayers>,
+) {
+    for desync in desyncs.read() {
+        let Some(state) = snapshots.0.get(&desync.frame) else {
+            warn!("no snapshot left of desynced frame {}", desync.frame);
+            continue;
+        };
+        let dump = DesyncDump {
+            local_checksum: format!("{:x}", desync.local_checksum),
+            remote_checksum: format!("{:x}", desync.remote_checksum),
+            state: state.clone(),
+        };
+        let json = serde_json::to_string_pretty(&dump).expect("dumps are plain json");
+        let handle = local_players.0.first().copied().unwrap_or_default();
+        write_dump(desync.frame, handle, &json);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_dump(frame: i32, handle: usize, json: &str) {
+    let path = std::path::Path::new(DUMP_DIR).join(format!("frame-{frame}-p{}.json", handle + 1));
+    let result = std::fs::create_dir_all(DUMP_DIR).and_then(|_| std::fs::write(&path, json));
+    match result {
+        Ok(()) => warn!(
+            "desync on frame {frame}, state written to {}",
+            path.display()
+        ),
+        Err(e) => error!("could not write desync dump {}: {e}", path.display()),
+    }
+}
+
+/// There is no disk to write to on the web, the console has to do.
+#[cfg(target_arch = "wasm32")]
+fn write_dump(frame: i32, handle: usize, json: &str) {
+    warn!("desync on frame {frame}, state of P{}:\n{json}", handle + 1);
+}
+
+/// A field that differs between two dumps.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+/// Lists every difference between two dumps, resources first, then the
+/// entities in spawn order with their components in registration order.
+pub fn differences(left: &FrameDump, right: &FrameDump) -> Vec<Difference> {
+    let mut diffs = Vec::new();
+    let (left, right) = (
+        serde_json::to_value(left).expect("dumps are plain json"),
+        serde_json::to_value(right).expect("dumps are plain json"),
+    );
+    for key in ["resources", "entities"] {
+        compare(
+            key.to_owned(),
+            Some(&left[key]),
+            Some(&right[key]),
+            &mut diffs,
+        );
+    }
+    diffs
+}
+
+fn compare(path: String, left: Option<&Value>, right: Option<&Value>, diffs: &mut Vec<Difference>) {
+    match (left, right) {
+        (Some(Value::Object(l)), Some(Value::Object(r))) => {
+            let added = r.keys().filter(|key| !l.contains_key(*key));
+            for key in l.keys().chain(added) {
+                compare(format!("{path}.{key}"), l.get(key), r.get(key), diffs);
+            }
+        }
+        (Some(Value::Array(l)), Some(Value::Array(r))) => {
+            for i in 0..l.len().max(r.len()) {
+                compare(format!("{path}[{i}]"), l.get(i), r.get(i), diffs);
+            }
+        }
+        (l, r) if l != r => diffs.push(Difference {
+            path,
+            left: l.cloned(),
+            right: r.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn entity(rollback: usize, velocity: Value) -> EntityDump {
+        let mut components = Map::new();
+        components.insert("Transform".to_owned(), json!({ "x": 1.0, "y": 2.0 }));
+        components.insert("Velocity".to_owned(), velocity);
+        EntityDump {
+            rollback,
+            components,
+        }
+    }
+
+    fn frame(entities: Vec<EntityDump>) -> FrameDump {
+        let mut resources = Map::new();
+        resources.insert("FrameCount".to_owned(), json!(30));
+        FrameDump {
+            frame: 30,
+            resources,
+            entities,
+        }
+    }
+
+    #[test]
+    fn equal_dumps_have_no_differences() {
+        let dump = frame(vec![entity(0, json!({ "x": 0.5 }))]);
+        assert_eq!(differences(&dump, &dump.clone()), vec![]);
+    }
+
+    #[test]
+    fn reports_the_entity_and_field_that_changed() {
+        let left = frame(vec![
+            entity(0, json!({ "x": 0.5, "y": 0.0 })),
+            entity(1, json!({ "x": 0.5, "y": 0.0 })),
+        ]);
+        let mut right = left.clone();
+        right.entities[1].components["Velocity"]["y"] = json!(0.25);
+
+        let diffs = differences(&left, &right);
+        assert_eq!(
+            diffs.first(),
+            Some(&Difference {
+                path: "entities[1].components.Velocity.y".to_owned(),
+                left: Some(json!(0.0)),
+                right: Some(json!(0.25)),
+            })
+        );
+        assert_eq!(diffs.len(), 1);
+    }
+
+    #[test]
+    fn reports_resources_before_entities() {
+        let left = frame(vec![entity(0, json!({ "x": 0.5 }))]);
+        let mut right = left.clone();
+        right.resources["FrameCount"] = json!(31);
+        right.entities[0].components["Velocity"]["x"] = json!(1.0);
+
+        let paths: Vec<_> = differences(&left, &right)
+            .into_iter()
+            .map(|diff| diff.path)
+            .collect();
+        assert_eq!(
+            paths,
+            ["resources.FrameCount", "entities[0].components.Velocity.x"]
+        );
+    }
+
+    #[test]
+    fn reports_missing_entities() {
+        let left = frame(vec![entity(0, json!({ "x": 0.5 }))]);
+        let right = frame(vec![]);
+
+        let diffs = differences(&left, &right);
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "entities[0]");
+        assert_eq!(diffs[0].right, None);
+    }
+}
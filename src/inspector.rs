@@ -0,0 +1,241 @@
+//! State inspector for local SyncTest rounds.
+//!
+//! Every simulated frame is recorded, and when a resimulation of a frame ends
+//! up in a different state than the first run, the round pauses and shows
+//! both. F3 pauses and resumes by hand, F4 advances a single frame while paused.
+
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+use bevy::prelude::*;
+use bevy_ggrs::{Rollback, RollbackFrameCount};
+
+use crate::{
+    boost::Boost,
+    checksum::Checksum,
+    menu::settings::SessionSettings,
+    round::{CarControls, Player, RoundUI, Velocity},
+    FontAssets, BUTTON_TEXT, DISABLED_BUTTON,
+};
+
+/// Recorded frames, only the ones SyncTest may still resimulate are of interest.
+const KEPT_FRAMES: i32 = 64;
+
+/// Everything the rollback schedule touches on one car.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CarState {
+    pub handle: usize,
+    pub transform: Transform,
+    pub velocity: Velocity,
+    pub controls: CarControls,
+    pub boost: Boost,
+    pub checksum: Checksum,
+}
+
+impl CarState {
+    fn describe(&self, out: &mut String, label: &str) {
+        let t = self.transform.translation;
+        let angle = self.transform.rotation.to_euler(EulerRot::XYZ).2;
+        let v = self.velocity.0;
+        writeln!(
+            out,
+            "P{} {label:<11} pos ({:.4}, {:.4}) rot {:.4} vel ({:.4}, {:.4}) accel {:.3} steer {:.3} boost {} checksum {:04x}",
+            self.handle + 1,
+            t.x,
+            t.y,
+            angle,
+            v.x,
+            v.y,
+            self.controls.accel,
+            self.controls.steer,
+            self.boost.frames,
+            self.checksum.value,
+        )
+        .unwrap();
+    }
+}
+
+pub struct Mismatch {
+    pub frame: i32,
+    pub original: Vec<CarState>,
+    pub resimulated: Vec<CarState>,
+}
+
+#[derive(Resource, Default)]
+pub struct Inspector {
+    frames: BTreeMap<i32, Vec<CarState>>,
+    latest_frame: i32,
+    pub mismatch: Option<Mismatch>,
+    pub paused: bool,
+    step: bool,
+}
+
+#[derive(Component)]
+pub struct InspectorText;
+
+pub fn setup_inspector(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands.insert_resource(Inspector::default());
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(16.),
+                bottom: Val::Px(16.),
+                ..Default::default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 16.,
+                    color: BUTTON_TEXT,
+                },
+            ),
+            background_color: Color::rgba(0., 0., 0., 0.7).into(),
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        })
+        .insert(InspectorText)
+        .insert(RoundUI);
+}
+
+/// Runs at the end of the [`bevy_ggrs::GgrsSchedule`], compares resimulated
+/// frames with their first run.
+pub fn inspect_frame(
+    frame: Res<RollbackFrameCount>,
+    query: Query<
+        (
+            &Player,
+            &Transform,
+            &Velocity,
+            &CarControls,
+            &Boost,
+            &Checksum,
+        ),
+        With<Rollback>,
+    >,
+    inspector: Option<ResMut<Inspector>>,
+) {
+    let Some(mut inspector) = inspector else {
+        return;
+    };
+    let frame: i32 = (*frame).into();
+
+    let mut cars: Vec<_> = query
+        .iter()
+        .map(|(p, t, v, c, boost, checksum)| CarState {
+            handle: p.handle,
+            transform: *t,
+            velocity: v.clone(),
+            controls: c.clone(),
+            boost: boost.clone(),
+            checksum: checksum.clone(),
+        })
+        .collect();
+    cars.sort_by_key(|car| car.handle);
+
+    match inspector.frames.get(&frame) {
+        Some(original) if *original != cars => {
+            warn!("frame {frame} changed when it was resimulated");
+            let original = original.clone();
+            inspector.mismatch = Some(Mismatch {
+                frame,
+                original,
+                resimulated: cars.clone(),
+            });
+            inspector.paused = true;
+        }
+        // resimulated just like the first time
+        Some(_) => return,
+        None => {}
+    }
+
+    inspector.latest_frame = inspector.latest_frame.max(frame);
+    let oldest = inspector.latest_frame - KEPT_FRAMES;
+    inspector.frames.insert(frame, cars);
+    inspector.frames.retain(|f, _| *f > oldest);
+}
+
+pub fn inspector_input(keys: Res<Input<KeyCode>>, mut inspector: ResMut<Inspector>) {
+    if keys.just_pressed(KeyCode::F3) {
+        inspector.paused = !inspector.paused;
+        if !inspector.paused {
+            inspector.mismatch = None;
+        }
+    }
+    if keys.just_pressed(KeyCode::F4) && inspector.paused {
+        inspector.step = true;
+    }
+}
+
+/// Holds the rollback schedule by pausing virtual time, and lets exactly one
+/// frame worth of time through when stepping.
+///
+/// Runs in [`First`] after the clocks were updated, before GGRS reads them.
+pub fn apply_pause(
+    mut inspector: ResMut<Inspector>,
+    settings: Res<SessionSettings>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut time: ResMut<Time>,
+) {
+    if inspector.paused != virtual_time.is_paused() {
+        if inspector.paused {
+            virtual_time.pause();
+        } else {
+            virtual_time.unpause();
+        }
+    }
+
+    if inspector.step {
+        inspector.step = false;
+        // a hair more than a frame, GGRS only steps once the time has fully passed
+        let frame = Duration::from_secs_f64(1. / settings.fps as f64);
+        time.advance_by(frame + Duration::from_micros(1));
+    }
+}
+
+pub fn update_inspector_display(
+    inspector: Res<Inspector>,
+    mut query: Query<(&mut Text, &mut Visibility), With<InspectorText>>,
+) {
+    if !inspector.is_changed() {
+        return;
+    }
+
+    for (mut text, mut visibility) in query.iter_mut() {
+        if !inspector.paused {
+            *visibility = Visibility::Hidden;
+            continue;
+        }
+        *visibility = Visibility::Inherited;
+
+        let mut value = String::new();
+        let color = match &inspector.mismatch {
+            Some(mismatch) => {
+                writeln!(value, "Mismatch on frame {}", mismatch.frame).unwrap();
+                for (original, resimulated) in mismatch.original.iter().zip(&mismatch.resimulated) {
+                    original.describe(&mut value, "original");
+                    resimulated.describe(&mut value, "resimulated");
+                }
+                DISABLED_BUTTON
+            }
+            None => {
+                let latest = inspector.frames.iter().next_back();
+                if let Some((frame, cars)) = latest {
+                    writeln!(value, "Paused on frame {frame}").unwrap();
+                    for car in cars {
+                        car.describe(&mut value, "");
+                    }
+                }
+                BUTTON_TEXT
+            }
+        };
+        value.push_str("F3 resume, F4 step one frame");
+        text.sections[0].value = value;
+        text.sections[0].style.color = color;
+    }
+}
+
+pub fn cleanup_inspector(mut commands: Commands, mut virtual_time: ResMut<Time<Virtual>>) {
+    commands.remove_resource::<Inspector>();
+    virtual_time.unpause();
+}
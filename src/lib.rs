@@ -0,0 +1,149 @@
+#![allow(clippy::type_complexity)]
+
+pub mod arena;
+pub mod boost;
+pub mod camera;
+pub mod car;
+pub mod checksum;
+pub mod combat;
+pub mod cli;
+pub mod debug;
+pub mod desync;
+pub mod inspector;
+pub mod map;
+pub mod menu;
+pub mod mode;
+pub mod netsim;
+pub mod ron_asset;
+pub mod round;
+pub mod share;
+#[cfg(all(feature = "local-signaling", not(target_arch = "wasm32")))]
+pub mod signaling;
+pub mod tag;
+pub mod tuning;
+
+use arena::bounce_players;
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+use bevy_ggrs::ggrs::Config;
+use bevy_ggrs::{GgrsApp, GgrsPlugin, GgrsSchedule};
+use bevy_matchbox::prelude::*;
+use boost::{update_boosts, Boost, Pickup};
+use checksum::{checksum_players, Checksum};
+use desync::{snapshot_frame, Desync, DumpApp, FrameSnapshots};
+use mode::{ModeSystems, ModesPlugin};
+use round::{
+    apply_inputs, increase_frame_count, move_players, update_velocity, FrameCount, Velocity,
+};
+use tuning::{apply_tuning_changes, CarTuning, TuningChanges};
+
+const NUM_PLAYERS: usize = 2;
+const MAX_PLAYERS: usize = 4;
+const FPS: usize = 60;
+const MAX_PREDICTION: usize = 12;
+const INPUT_DELAY: usize = 2;
+const CHECK_DISTANCE: usize = 2;
+/// Every this many frames, peers compare checksums.
+const DESYNC_INTERVAL: u32 = 10;
+
+const DISABLED_BUTTON: Color = Color::rgb(0.8, 0.5, 0.5);
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+const BUTTON_TEXT: Color = Color::rgb(0.9, 0.9, 0.9);
+
+#[derive(States, Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub enum AppState {
+    #[default]
+    AssetLoading,
+    MenuMain,
+    MenuOnline,
+    MenuConnect,
+    MenuLobby,
+    MenuSettings,
+    MenuMaps,
+    MenuCars,
+    RoundLocal,
+    RoundOnline,
+    Win,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct ImageAssets {
+    #[asset(path = "images/ggrs_logo.png")]
+    pub ggrs_logo: Handle<Image>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct FontAssets {
+    #[asset(path = "fonts/FiraSans-Bold.ttf")]
+    pub default_font: Handle<Font>,
+}
+
+#[derive(Debug)]
+pub struct GGRSConfig;
+impl Config for GGRSConfig {
+    type Input = round::Input;
+    type State = u8;
+    type Address = PeerId;
+}
+
+/// The deterministic part of the game: everything GGRS saves, loads and advances.
+///
+/// Reading the local inputs is left to the app, see [`round::input`].
+pub struct RollbackPlugin;
+
+impl Plugin for RollbackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(GgrsPlugin::<GGRSConfig>::default())
+            .set_rollback_schedule_fps(FPS)
+            .rollback_component_with_clone::<Transform>()
+            .rollback_component_with_reflect::<Velocity>()
+            .rollback_component_with_reflect::<Checksum>()
+            .rollback_component_with_reflect::<Boost>()
+            .rollback_component_with_reflect::<Pickup>()
+            .rollback_resource_with_reflect::<FrameCount>()
+            .rollback_resource_with_clone::<CarTuning>()
+            .checksum_component_with_hash::<Checksum>()
+            .checksum_component_with_hash::<Boost>()
+            .checksum_component_with_hash::<Pickup>()
+            .checksum_resource_with_hash::<CarTuning>()
+            // desync dumps cover the same state as the rollbacks
+            .dump_component::<Transform>()
+            .dump_component::<Velocity>()
+            .dump_component::<Boost>()
+            .dump_component::<Pickup>()
+            .dump_component::<Checksum>()
+            .dump_resource::<FrameCount>()
+            .dump_resource_with_serde::<CarTuning>()
+            .init_resource::<FrameSnapshots>()
+            .init_resource::<CarTuning>()
+            .init_resource::<TuningChanges>()
+            .add_event::<Desync>()
+            .add_systems(
+                GgrsSchedule,
+                (
+                    apply_tuning_changes,
+                    apply_inputs,
+                    update_boosts,
+                    update_velocity,
+                    move_players,
+                    bounce_players,
+                    increase_frame_count,
+                    checksum_players,
+                    // exclusive, but it only reads the state the frame ended up in
+                    snapshot_frame.ambiguous_with_all(),
+                )
+                    .chain(),
+            )
+            // only the systems of one mode run in a round
+            .configure_sets(
+                GgrsSchedule,
+                ModeSystems
+                    .after(bounce_players)
+                    .before(increase_frame_count)
+                    .ambiguous_with(ModeSystems),
+            )
+            .add_plugins(ModesPlugin);
+    }
+}
@@ -1,102 +1,100 @@
-mod checksum;
-mod menu;
-mod round;
-
-use bevy::prelude::*;
+use bevy::{prelude::*, time::TimeSystem};
 use bevy_asset_loader::prelude::*;
-use bevy_ggrs::ggrs::Config;
-use bevy_ggrs::{GgrsApp, GgrsPlugin, GgrsSchedule, ReadInputs};
-use bevy_matchbox::prelude::*;
-use checksum::{checksum_players, Checksum};
-use menu::{
-    connect::{create_matchbox_socket, update_matchbox_socket},
-    online::{update_lobby_btn, update_lobby_id, update_lobby_id_display},
-};
-use round::{
-    apply_inputs, check_win, increase_frame_count, move_players, print_p2p_events, setup_round, spawn_players, update_velocity, FrameCount, Velocity
+use bevy_ggrs::{GgrsSchedule, LoadWorld, ReadInputs};
+use bevy_ggrs_demo::{
+    arena::add_obstacle_visuals,
+    boost::update_pickup_visibility,
+    camera::{cycle_camera_mode, move_cameras, update_cameras, update_viewports, CameraMode},
+    car::SelectedCars,
+    checksum::checksum_players,
+    cli::{self, LaunchOptions},
+    debug::{
+        self, count_rollback, record_frame, reset_rollback_stats, toggle_overlay, update_ghosts,
+        update_stats_display, DebugOverlay, FrameHistory, RollbackStats,
+    },
+    desync::{dump_desyncs, reset_snapshots},
+    inspector::{
+        apply_pause, cleanup_inspector, inspect_frame, inspector_input, setup_inspector,
+        update_inspector_display,
+    },
+    map::{Map, MapAssets, MapLoader, SelectedMap},
+    mode::{in_round, SelectedMode},
+    menu::{
+        self,
+        chat::{receive_chat, update_chat_display, update_chat_input, Chat},
+        connect::{create_matchbox_socket, update_matchbox_socket, MatchboxAddr},
+        lobby::{receive_rejects, setup_lobby, update_lobby, update_lobby_display},
+        online::{
+            update_input_focus, update_input_focus_display, update_lobby_btn, update_lobby_id,
+            update_lobby_id_display, update_player_name, update_player_name_display, PlayerName,
+        },
+        settings::{update_settings_display, SessionSettings},
+    },
+    round::{self, print_p2p_events, setup_round, spawn_players, update_name_labels},
+    share::Clipboard,
+    tuning::{reload_tuning, reset_tuning_changes, CarTuning, TuningAssets, TuningLoader},
+    AppState, FontAssets, ImageAssets, RollbackPlugin,
 };
 
-const NUM_PLAYERS: usize = 2;
-const FPS: usize = 60;
-const MAX_PREDICTION: usize = 12;
-const INPUT_DELAY: usize = 2;
-const CHECK_DISTANCE: usize = 2;
-
-const DISABLED_BUTTON: Color = Color::rgb(0.8, 0.5, 0.5);
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
-const BUTTON_TEXT: Color = Color::rgb(0.9, 0.9, 0.9);
-
-#[derive(States, Debug, Clone, Eq, PartialEq, Hash, Default)]
-pub enum AppState {
-    #[default]
-    AssetLoading,
-    MenuMain,
-    MenuOnline,
-    MenuConnect,
-    RoundLocal,
-    RoundOnline,
-    Win,
-}
-
-#[derive(AssetCollection, Resource)]
-pub struct ImageAssets {
-    #[asset(path = "images/ggrs_logo.png")]
-    pub ggrs_logo: Handle<Image>,
-}
-
-#[derive(AssetCollection, Resource)]
-pub struct FontAssets {
-    #[asset(path = "fonts/FiraSans-Bold.ttf")]
-    pub default_font: Handle<Font>,
-}
-
-#[derive(Debug)]
-pub struct GGRSConfig;
-impl Config for GGRSConfig {
-    type Input = round::Input;
-    type State = u8;
-    type Address = PeerId;
-}
-
 fn main() {
+    #[cfg(not(target_arch = "wasm32"))]
+    let options = LaunchOptions::from_env();
+    #[cfg(target_arch = "wasm32")]
+    let options = LaunchOptions::from_url();
+
     let mut app = App::new();
 
-    app.add_plugins(DefaultPlugins)
+    // picks up changes to the car tuning while the game runs
+    let assets = AssetPlugin {
+        watch_for_changes_override: Some(cfg!(not(target_arch = "wasm32"))),
+        ..Default::default()
+    };
+
+    app.add_plugins(DefaultPlugins.set(assets))
         .add_state::<AppState>()
+        .init_resource::<SessionSettings>()
+        .init_resource::<MatchboxAddr>()
+        .init_resource::<PlayerName>()
+        .init_resource::<Chat>()
+        .init_resource::<DebugOverlay>()
+        .init_resource::<RollbackStats>()
+        .init_resource::<FrameHistory>()
+        .init_resource::<SelectedMap>()
+        .init_resource::<SelectedMode>()
+        .init_resource::<SelectedCars>()
+        .init_resource::<CameraMode>()
+        .init_non_send_resource::<Clipboard>()
         // asset loading
+        .init_asset::<Map>()
+        .init_asset_loader::<MapLoader>()
+        .init_asset::<CarTuning>()
+        .init_asset_loader::<TuningLoader>()
         .add_loading_state(
             LoadingState::new(AppState::AssetLoading)
                 .continue_to_state(AppState::MenuMain)
                 .load_collection::<FontAssets>()
-                .load_collection::<ImageAssets>(),
+                .load_collection::<ImageAssets>()
+                .load_collection::<MapAssets>()
+                .load_collection::<TuningAssets>(),
         )
         // ggrs plugin
-        .add_plugins(GgrsPlugin::<GGRSConfig>::default())
-        .set_rollback_schedule_fps(FPS)
+        .add_plugins(RollbackPlugin)
         .add_systems(ReadInputs,round::input)
-        .rollback_component_with_clone::<Transform>()
-        .rollback_component_with_reflect::<Velocity>()
-        .rollback_component_with_reflect::<Checksum>()
-        .rollback_resource_with_reflect::<FrameCount>()
-        // rollback schedule
-        .add_systems(
-            GgrsSchedule,
-            (
-                apply_inputs,
-                update_velocity,
-                move_players,
-                increase_frame_count,
-                checksum_players,
-            )
-                .chain(),
-        )
+        // rollback debug overlay
+        .add_systems(LoadWorld, count_rollback)
+        .add_systems(GgrsSchedule, record_frame.after(checksum_players))
+        // synctest inspector
+        .add_systems(GgrsSchedule, inspect_frame.after(checksum_players))
+        .add_systems(First, apply_pause.after(TimeSystem).run_if(in_state(AppState::RoundLocal)))
+        // online rounds keep the tuning everyone agreed on in the lobby
+        .add_systems(Update, reload_tuning.run_if(not(in_state(AppState::RoundOnline))))
+        // chat keeps collecting messages while the socket is open, even mid round
+        .add_systems(Update, receive_chat)
         // main menu
-        .add_systems(OnEnter(AppState::MenuMain), menu::main::setup_ui)
+        .add_systems(OnEnter(AppState::MenuMain), (menu::main::setup_ui, cli::launch))
         .add_systems(
             Update,
-            (menu::main::btn_visuals, menu::main::btn_listeners)
+            (menu::main::update_mode_display, menu::main::btn_visuals, menu::main::btn_listeners)
                 .run_if(in_state(AppState::MenuMain)),
         )
         .add_systems(OnExit(AppState::MenuMain), menu::main::cleanup_ui)
@@ -105,6 +103,10 @@ fn main() {
         .add_systems(
             Update,
             (
+                update_input_focus,
+                update_input_focus_display,
+                update_player_name,
+                update_player_name_display,
                 update_lobby_id,
                 update_lobby_id_display,
                 update_lobby_btn,
@@ -114,6 +116,34 @@ fn main() {
                 .run_if(in_state(AppState::MenuOnline)),
         )
         .add_systems(OnExit(AppState::MenuOnline), menu::online::cleanup_ui)
+        // settings menu
+        .add_systems(OnEnter(AppState::MenuSettings), menu::settings::setup_ui)
+        .add_systems(
+            Update,
+            (
+                update_settings_display,
+                menu::settings::btn_visuals,
+                menu::settings::btn_listeners,
+            )
+                .run_if(in_state(AppState::MenuSettings)),
+        )
+        .add_systems(OnExit(AppState::MenuSettings), menu::settings::cleanup_ui)
+        // map select menu
+        .add_systems(OnEnter(AppState::MenuMaps), menu::maps::setup_ui)
+        .add_systems(
+            Update,
+            (menu::maps::btn_visuals, menu::maps::btn_listeners)
+                .run_if(in_state(AppState::MenuMaps)),
+        )
+        .add_systems(OnExit(AppState::MenuMaps), menu::maps::cleanup_ui)
+        // car select menu
+        .add_systems(OnEnter(AppState::MenuCars), menu::cars::setup_ui)
+        .add_systems(
+            Update,
+            (menu::cars::update_cars_display, menu::cars::btn_visuals, menu::cars::btn_listeners)
+                .run_if(in_state(AppState::MenuCars)),
+        )
+        .add_systems(OnExit(AppState::MenuCars), menu::cars::cleanup_ui)
         // connect menu
         .add_systems(
             OnEnter(AppState::MenuConnect),
@@ -132,24 +162,64 @@ fn main() {
             OnExit(AppState::MenuConnect),
             (menu::connect::cleanup, menu::connect::cleanup_ui),
         )
+        // lobby menu
+        .add_systems(
+            OnEnter(AppState::MenuLobby),
+            (setup_lobby, menu::lobby::setup_ui),
+        )
+        .add_systems(
+            Update,
+            (
+                update_lobby,
+                update_lobby_display,
+                update_chat_input,
+                update_chat_display,
+                menu::lobby::btn_visuals,
+                menu::lobby::btn_listeners,
+                menu::chat::btn_visuals,
+                menu::chat::btn_listeners,
+            )
+                .chain()
+                .run_if(in_state(AppState::MenuLobby)),
+        )
+        .add_systems(OnExit(AppState::MenuLobby), menu::lobby::cleanup_ui)
         // win menu
         .add_systems(OnEnter(AppState::Win), menu::win::setup_ui)
         .add_systems(
             Update,
             (menu::win::btn_visuals, menu::win::btn_listeners).run_if(in_state(AppState::Win)),
         )
+        .add_systems(
+            Update,
+            (
+                update_chat_input,
+                update_chat_display,
+                menu::chat::btn_visuals,
+                menu::chat::btn_listeners,
+            )
+                .run_if(in_state(AppState::Win))
+                .run_if(menu::win::online_match),
+        )
         .add_systems(OnExit(AppState::Win), menu::win::cleanup_ui)
+        // cameras, in local and online rounds alike
+        .add_systems(Update, (cycle_camera_mode, update_cameras, apply_deferred, update_viewports, move_cameras).chain().run_if(in_round()))
         // local round
-        .add_systems(OnEnter(AppState::RoundLocal), (round::setup_ui, setup_round, spawn_players))
-        .add_systems(Update, (check_win, round::btn_visuals, round::btn_listeners).run_if(in_state(AppState::RoundLocal)))
-        .add_systems(OnExit(AppState::RoundLocal), (round::cleanup, round::cleanup_ui))
+        .add_systems(OnEnter(AppState::RoundLocal), (round::setup_ui, setup_round, spawn_players, debug::setup_ui, reset_rollback_stats, reset_snapshots, setup_inspector))
+        .add_systems(Update, (update_name_labels, update_pickup_visibility, add_obstacle_visuals, round::btn_visuals, round::btn_listeners).run_if(in_state(AppState::RoundLocal)))
+        .add_systems(Update, (inspector_input, update_inspector_display).chain().run_if(in_state(AppState::RoundLocal)))
+        .add_systems(Update, (toggle_overlay, update_stats_display, update_ghosts).run_if(in_state(AppState::RoundLocal)))
+        .add_systems(OnExit(AppState::RoundLocal), (round::cleanup, round::cleanup_ui, cleanup_inspector, reset_tuning_changes))
         // online round
-        .add_systems(OnEnter(AppState::RoundOnline), (round::setup_ui, setup_round, spawn_players))
+        .add_systems(OnEnter(AppState::RoundOnline), (round::setup_ui, setup_round, spawn_players, debug::setup_ui, reset_rollback_stats, reset_snapshots))
+        .add_systems(Update, (toggle_overlay, update_stats_display, update_ghosts).run_if(in_state(AppState::RoundOnline)))
         .add_systems(
             Update,
-            (check_win, print_p2p_events, round::btn_visuals, round::btn_listeners).run_if(in_state(AppState::RoundOnline)),
+            (print_p2p_events, dump_desyncs.after(print_p2p_events), receive_rejects, update_name_labels, update_pickup_visibility, add_obstacle_visuals, round::btn_visuals, round::btn_listeners).run_if(in_state(AppState::RoundOnline)),
         )
         .add_systems(OnExit(AppState::RoundOnline), (round::cleanup, round::cleanup_ui));
 
+    options.apply(&mut app);
+    app.insert_resource(options);
+
     app.run();
 }
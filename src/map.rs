@@ -0,0 +1,141 @@
+//! Maps, loaded from `.map.ron` files in `assets/maps`.
+//!
+//! A map describes the arena bounds, where the cars start, its obstacles,
+//! checkpoints, boost pads and pickups. Every map carries a hash of the file it
+//! was loaded from, so peers can make sure they are playing the same one.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_asset_loader::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    arena::ObstacleDef,
+    ron_asset::{fnv1a, RonAsset, RonLoadError, RonLoader},
+    MAX_PLAYERS,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub fn vec2(&self) -> Vec2 {
+        Vec2::new(self.x, self.y)
+    }
+}
+
+/// Where a car starts, facing `angle` degrees counterclockwise from up.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub angle: f32,
+}
+
+impl SpawnPoint {
+    pub fn transform(&self) -> Transform {
+        Transform::from_xyz(self.x, self.y, 1.)
+            .with_rotation(Quat::from_rotation_z(self.angle.to_radians()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct Checkpoint {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+/// Also inserted as a resource for the duration of a round.
+#[derive(Asset, TypePath, Resource, Clone, Debug, PartialEq, Deserialize)]
+pub struct Map {
+    pub name: String,
+    pub width: f32,
+    pub height: f32,
+    /// One per player, in handle order.
+    pub spawns: Vec<SpawnPoint>,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDef>,
+    #[serde(default)]
+    pub checkpoints: Vec<Checkpoint>,
+    #[serde(default)]
+    pub boost_pads: Vec<Point>,
+    #[serde(default)]
+    pub pickups: Vec<Point>,
+    /// Of the file the map was loaded from.
+    #[serde(skip)]
+    pub hash: u64,
+}
+
+impl RonAsset for Map {
+    const EXTENSIONS: &'static [&'static str] = &["map.ron"];
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, RonLoadError> {
+        let mut map: Map = ron::de::from_bytes(bytes)?;
+        if map.width <= 0. || map.height <= 0. {
+            return Err(RonLoadError::Invalid(
+                "the size has to be positive".to_owned(),
+            ));
+        }
+        if map.spawns.len() < MAX_PLAYERS {
+            return Err(RonLoadError::Invalid(format!(
+                "{} spawn points are needed, found {}",
+                MAX_PLAYERS,
+                map.spawns.len()
+            )));
+        }
+        map.hash = fnv1a(bytes);
+        Ok(map)
+    }
+}
+
+impl Map {
+    pub fn size(&self) -> Vec2 {
+        Vec2::new(self.width, self.height)
+    }
+}
+
+pub type MapLoader = RonLoader<Map>;
+
+#[derive(AssetCollection, Resource)]
+pub struct MapAssets {
+    #[asset(
+        paths("maps/classic.map.ron", "maps/open.map.ron", "maps/chicane.map.ron"),
+        collection(typed)
+    )]
+    pub maps: Vec<Handle<Map>>,
+}
+
+/// Index into the [`MapAssets`] of the map the next round is played on.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelectedMap(pub usize);
+
+/// All loaded maps and the selected one.
+#[derive(SystemParam)]
+pub struct Maps<'w> {
+    assets: Res<'w, MapAssets>,
+    maps: Res<'w, Assets<Map>>,
+    selected: Res<'w, SelectedMap>,
+}
+
+impl<'w> Maps<'w> {
+    pub fn iter(&self) -> impl Iterator<Item = &Map> {
+        self.assets
+            .maps
+            .iter()
+            .map(|handle| self.maps.get(handle).expect("maps are loaded up front"))
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected.0.min(self.assets.maps.len() - 1)
+    }
+
+    pub fn selected(&self) -> &Map {
+        self.iter()
+            .nth(self.selected_index())
+            .expect("there is at least one map")
+    }
+}
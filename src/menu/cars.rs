@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+
+use crate::{
+    car::SelectedCars, AppState, FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON,
+    PRESSED_BUTTON,
+};
+
+use super::settings::SessionSettings;
+
+#[derive(Component)]
+pub struct MenuCarsUI;
+
+#[derive(Component)]
+pub enum MenuCarsBtn {
+    /// Cycles the class of the local player with this handle.
+    Car(usize),
+    Back,
+}
+
+#[derive(Component)]
+pub struct CarBtnText(usize);
+
+fn car_label(cars: &SelectedCars, handle: usize) -> String {
+    format!("Player {}: {}", handle + 1, cars.0[handle].name())
+}
+
+pub fn setup_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    settings: Res<SessionSettings>,
+    cars: Res<SelectedCars>,
+) {
+    // ui camera
+    commands.spawn(Camera2dBundle::default()).insert(MenuCarsUI);
+
+    let text_style = TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size: 40.0,
+        color: BUTTON_TEXT,
+    };
+    let btn = ButtonBundle {
+        style: Style {
+            width: Val::Px(400.0),
+            height: Val::Px(65.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(16.)),
+            padding: UiRect::all(Val::Px(16.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    };
+
+    // root node
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            background_color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            // one button per local player, online only the first one counts
+            for handle in 0..settings.num_players.min(cars.0.len()) {
+                parent
+                    .spawn(btn.clone())
+                    .with_children(|parent| {
+                        parent
+                            .spawn(TextBundle::from_section(
+                                car_label(&cars, handle),
+                                text_style.clone(),
+                            ))
+                            .insert(CarBtnText(handle));
+                    })
+                    .insert(MenuCarsBtn::Car(handle));
+            }
+
+            // back button
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Back to Menu", text_style.clone()));
+                })
+                .insert(MenuCarsBtn::Back);
+        })
+        .insert(MenuCarsUI);
+}
+
+pub fn update_cars_display(cars: Res<SelectedCars>, mut query: Query<(&mut Text, &CarBtnText)>) {
+    if !cars.is_changed() {
+        return;
+    }
+    for (mut text, CarBtnText(handle)) in query.iter_mut() {
+        text.sections[0].value = car_label(&cars, *handle);
+    }
+}
+
+pub fn btn_visuals(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuCarsBtn>),
+    >,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<NextState<AppState>>,
+    mut cars: ResMut<SelectedCars>,
+    mut interaction_query: Query<(&Interaction, &MenuCarsBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Pressed = *interaction {
+            match btn {
+                MenuCarsBtn::Car(handle) => {
+                    cars.0[*handle] = cars.0[*handle].next();
+                }
+                MenuCarsBtn::Back => {
+                    state.set(AppState::MenuMain);
+                }
+            }
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuCarsUI>>, mut commands: Commands) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
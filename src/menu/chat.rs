@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_matchbox::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    FontAssets, BUTTON_TEXT, DISABLED_BUTTON, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+use super::{
+    connect::CHAT_CHANNEL,
+    online::{ctrl_pressed, sanitize_name, PlayerName},
+};
+
+pub const MAX_CHAT_LEN: usize = 80;
+const MAX_CHAT_HISTORY: usize = 8;
+
+#[derive(Component)]
+pub enum ChatBtn {
+    Mute,
+}
+
+#[derive(Component)]
+pub struct ChatHistoryText;
+
+#[derive(Component)]
+pub struct ChatInputText;
+
+#[derive(Component)]
+pub struct MuteBtnText;
+
+/// A line of chat, sent on its own reliable channel so it can outlive the lobby.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChatMessage {
+    pub name: String,
+    pub text: String,
+}
+
+impl ChatMessage {
+    pub fn to_packet(&self) -> Box<[u8]> {
+        bincode::serialize(self)
+            .expect("Could not serialize chat message")
+            .into_boxed_slice()
+    }
+
+    pub fn from_packet(packet: &[u8]) -> Option<Self> {
+        bincode::deserialize(packet).ok()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Chat {
+    history: VecDeque<ChatMessage>,
+    input: String,
+    /// Drops incoming messages, our own are still shown.
+    muted: bool,
+}
+
+impl Chat {
+    /// Forgets the conversation, but keeps the mute setting.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.input.clear();
+    }
+
+    fn push(&mut self, message: ChatMessage) {
+        if self.history.len() >= MAX_CHAT_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(message);
+    }
+}
+
+/// Strips control characters and cuts the message down to [`MAX_CHAT_LEN`].
+pub fn sanitize_chat_text(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_LEN)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Spawns the chat panel as its own root node in the bottom right corner of the screen.
+pub fn spawn_chat_panel<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    font_assets: &FontAssets,
+) -> EntityCommands<'w, 's, 'a> {
+    let text_style = TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size: 20.,
+        color: BUTTON_TEXT,
+    };
+
+    let mut panel = commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            right: Val::Px(16.),
+            bottom: Val::Px(16.),
+            width: Val::Px(400.),
+            flex_direction: FlexDirection::Column,
+            padding: UiRect::all(Val::Px(8.)),
+            ..Default::default()
+        },
+        background_color: Color::rgba(0., 0., 0., 0.5).into(),
+        ..Default::default()
+    });
+    panel.with_children(|parent| {
+        // received and sent messages
+        parent
+            .spawn(TextBundle::from_section("", text_style.clone()))
+            .insert(ChatHistoryText);
+        // message being typed
+        parent
+            .spawn(TextBundle::from_sections([
+                TextSection::new(
+                    "> ",
+                    TextStyle {
+                        color: PRESSED_BUTTON,
+                        ..text_style.clone()
+                    },
+                ),
+                TextSection::new("", text_style.clone()),
+            ]))
+            .insert(ChatInputText);
+        // mute button
+        parent
+            .spawn(ButtonBundle {
+                style: Style {
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    margin: UiRect::top(Val::Px(8.)),
+                    padding: UiRect::all(Val::Px(4.)),
+                    ..Default::default()
+                },
+                background_color: NORMAL_BUTTON.into(),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                parent
+                    .spawn(TextBundle::from_section("Mute chat", text_style.clone()))
+                    .insert(MuteBtnText);
+            })
+            .insert(ChatBtn::Mute);
+    });
+    panel
+}
+
+/// Collects incoming messages for as long as the socket is around, so messages
+/// sent during a round show up on the win screen.
+pub fn receive_chat(
+    socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    mut chat: ResMut<Chat>,
+) {
+    let Some(mut socket) = socket else {
+        return;
+    };
+    for (peer, packet) in socket.channel_mut(CHAT_CHANNEL).receive() {
+        let Some(message) = ChatMessage::from_packet(&packet) else {
+            warn!("peer {peer} sent an invalid chat message");
+            continue;
+        };
+        if chat.muted {
+            continue;
+        }
+        let text = sanitize_chat_text(&message.text);
+        if !text.is_empty() {
+            let name = sanitize_name(&message.name);
+            chat.push(ChatMessage { name, text });
+        }
+    }
+}
+
+pub fn update_chat_input(
+    mut char_evr: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    socket: Option<ResMut<MatchboxSocket<MultipleChannels>>>,
+    player_name: Res<PlayerName>,
+    mut chat: ResMut<Chat>,
+) {
+    if ctrl_pressed(&keys) {
+        char_evr.clear();
+        return;
+    }
+    for ev in char_evr.read() {
+        if !ev.char.is_control() && chat.input.chars().count() < MAX_CHAT_LEN {
+            chat.input.push(ev.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        chat.input.pop();
+    }
+
+    if !keys.just_pressed(KeyCode::Return) {
+        return;
+    }
+    let text = sanitize_chat_text(&chat.input);
+    chat.input.clear();
+    let Some(mut socket) = socket else {
+        return;
+    };
+    if text.is_empty() {
+        return;
+    }
+
+    let message = ChatMessage {
+        name: sanitize_name(&player_name.0),
+        text,
+    };
+    let packet = message.to_packet();
+    let peers: Vec<_> = socket.connected_peers().collect();
+    for peer in peers {
+        socket.channel_mut(CHAT_CHANNEL).send(packet.clone(), peer);
+    }
+    chat.push(message);
+}
+
+pub fn update_chat_display(
+    chat: Res<Chat>,
+    added_query: Query<(), Added<ChatHistoryText>>,
+    mut history_query: Query<&mut Text, (With<ChatHistoryText>, Without<ChatInputText>)>,
+    mut input_query: Query<&mut Text, (With<ChatInputText>, Without<MuteBtnText>)>,
+    mut mute_query: Query<&mut Text, (With<MuteBtnText>, Without<ChatHistoryText>)>,
+) {
+    // also fill in freshly spawned panels
+    if !chat.is_changed() && added_query.is_empty() {
+        return;
+    }
+
+    let history = chat
+        .history
+        .iter()
+        .map(|m| {
+            let name = if m.name.is_empty() { "Player" } else { &m.name };
+            format!("{name}: {}", m.text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    for mut text in history_query.iter_mut() {
+        text.sections[0].value = history.clone();
+    }
+    for mut text in input_query.iter_mut() {
+        text.sections[1].value = chat.input.clone();
+    }
+    for mut text in mute_query.iter_mut() {
+        text.sections[0].value = if chat.muted {
+            "Unmute chat".to_owned()
+        } else {
+            "Mute chat".to_owned()
+        };
+        text.sections[0].style.color = if chat.muted {
+            DISABLED_BUTTON
+        } else {
+            BUTTON_TEXT
+        };
+    }
+}
+
+pub fn btn_visuals(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<ChatBtn>),
+    >,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut chat: ResMut<Chat>,
+    mut interaction_query: Query<(&Interaction, &ChatBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Pressed = *interaction {
+            match btn {
+                ChatBtn::Mute => {
+                    chat.muted = !chat.muted;
+                }
+            }
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use bevy::prelude::*;
-use bevy_ggrs::ggrs::{PlayerType, SessionBuilder};
-use bevy_ggrs::{LocalPlayers, Session};
 use bevy_matchbox::prelude::*;
 
 use crate::{
-    AppState, FontAssets, GGRSConfig, BUTTON_TEXT, FPS, HOVERED_BUTTON, INPUT_DELAY,
-    MAX_PREDICTION, NORMAL_BUTTON, NUM_PLAYERS, PRESSED_BUTTON,
+    share::share_url, AppState, FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON,
+    PRESSED_BUTTON,
 };
 
-const MATCHBOX_ADDR: &str = "ws://127.0.0.1:3536";
+use super::{chat::Chat, online::sanitize_lobby_code, settings::SessionSettings};
+
+pub const MATCHBOX_ADDR: &str = "ws://127.0.0.1:3536";
 // const MATCHBOX_ADDR: &str = "wss://match.gschup.dev";
 
+pub const GGRS_CHANNEL: usize = 0;
+pub const LOBBY_CHANNEL: usize = 1;
+pub const CHAT_CHANNEL: usize = 2;
+
 #[derive(Component)]
 pub struct MenuConnectUI;
 
@@ -22,20 +26,62 @@ pub enum MenuConnectBtn {
 #[derive(Resource)]
 pub struct ConnectData {
     pub lobby_id: String,
+    /// The code other players can join with, `None` for quick matches.
+    pub lobby_code: Option<String>,
+}
+
+impl ConnectData {
+    /// Joins the private room for the given lobby code.
+    pub fn lobby(code: &str) -> Self {
+        let code = sanitize_lobby_code(code);
+        Self {
+            lobby_id: format!("bevy{code}"),
+            lobby_code: Some(code),
+        }
+    }
+
+    /// Lets the signaling server group us with the next `num_players` peers.
+    pub fn quick_match(num_players: usize) -> Self {
+        Self {
+            lobby_id: format!("bevy?next={num_players}"),
+            lobby_code: None,
+        }
+    }
+}
+
+/// Address of the matchbox signaling server rooms are created on.
+#[derive(Resource)]
+pub struct MatchboxAddr(pub String);
+
+impl Default for MatchboxAddr {
+    fn default() -> Self {
+        Self(MATCHBOX_ADDR.to_owned())
+    }
 }
 
-pub fn create_matchbox_socket(mut commands: Commands, connect_data: Res<ConnectData>) {
+pub fn create_matchbox_socket(
+    mut commands: Commands,
+    connect_data: Res<ConnectData>,
+    matchbox_addr: Res<MatchboxAddr>,
+    mut chat: ResMut<Chat>,
+) {
     let lobby_id = &connect_data.lobby_id;
-    let room_url = format!("{MATCHBOX_ADDR}/{lobby_id}");
-    let socket: MatchboxSocket<SingleChannel> = MatchboxSocket::new_ggrs(room_url);
+    let room_url = format!("{}/{lobby_id}", matchbox_addr.0);
+    let socket = MatchboxSocket::from(
+        WebRtcSocketBuilder::new(room_url)
+            .add_ggrs_channel()
+            .add_reliable_channel()
+            .add_reliable_channel(),
+    );
     commands.insert_resource(socket);
+    chat.clear();
     commands.remove_resource::<ConnectData>();
 }
 
 pub fn update_matchbox_socket(
-    mut commands: Commands,
     mut state: ResMut<NextState<AppState>>,
-    mut socket: ResMut<MatchboxSocket<SingleChannel>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    settings: Res<SessionSettings>,
 ) {
     // regularly call update_peers to update the list of connected peers
     for (peer, new_state) in socket.update_peers() {
@@ -46,37 +92,9 @@ pub fn update_matchbox_socket(
         }
     }
 
-    if socket.players().len() >= NUM_PLAYERS {
-        // create a new ggrs session
-        let mut sess_build = SessionBuilder::<GGRSConfig>::new()
-            .with_num_players(NUM_PLAYERS)
-            .with_max_prediction_window(MAX_PREDICTION)
-            .expect("Invalid prediction window")
-            .with_fps(FPS)
-            .expect("Invalid FPS")
-            .with_input_delay(INPUT_DELAY);
-
-        // add players
-        let mut handles = Vec::new();
-        for (i, player_type) in socket.players().iter().enumerate() {
-            if *player_type == PlayerType::Local {
-                handles.push(i);
-            }
-            sess_build = sess_build
-                .add_player(player_type.clone(), i)
-                .expect("Invalid player added.");
-        }
-
-        // start the GGRS session
-        let channel = socket.take_channel(0).unwrap();
-        let sess = sess_build
-            .start_p2p_session(channel)
-            .expect("Session could not be created.");
-
-        // insert session as resource and switch state
-        commands.insert_resource(Session::P2P(sess));
-        commands.insert_resource(LocalPlayers(handles));
-        state.set(AppState::RoundOnline);
+    // everyone is here, the lobby decides when to start
+    if socket.players().len() >= settings.num_players {
+        state.set(AppState::MenuLobby);
     }
 }
 
@@ -85,7 +103,11 @@ pub fn cleanup(mut _commands: Commands) {
     // commands.remove_resource::<MatchboxSocket<SingleChannel>>();
 }
 
-pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+pub fn setup_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    connect_data: Res<ConnectData>,
+) {
     // ui camera
     commands
         .spawn(Camera2dBundle::default())
@@ -113,6 +135,29 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                     color: BUTTON_TEXT,
                 },
             ));
+            if let Some(code) = &connect_data.lobby_code {
+                let share_text = match share_url(code) {
+                    Some(url) => format!("Share this link: {url}"),
+                    None => "Share this lobby code with your opponent".to_owned(),
+                };
+                parent.spawn(TextBundle::from_section(
+                    share_text,
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 24.,
+                        color: BUTTON_TEXT,
+                    },
+                ));
+                // lobby code, big enough to read out to a friend
+                parent.spawn(TextBundle::from_section(
+                    code.clone(),
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 96.,
+                        color: BUTTON_TEXT,
+                    },
+                ));
+            }
             // back button
             parent
                 .spawn(ButtonBundle {
@@ -183,4 +228,4 @@ pub fn cleanup_ui(query: Query<Entity, With<MenuConnectUI>>, mut commands: Comma
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
-}
\ No newline at end of file
+}
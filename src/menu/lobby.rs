@@ -0,0 +1,848 @@
+use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, utils::HashMap};
+use bevy_ggrs::ggrs::PlayerType;
+use bevy_ggrs::{LocalPlayers, Session};
+use bevy_matchbox::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{CarClass, PlayerCars, SelectedCars},
+    map::{Map, Maps},
+    mode::{GameMode, SelectedMode},
+    netsim::{NetworkConditions, SimulatedSocket},
+    round::{PlayerColors, PlayerNames, PLAYER_COLORS},
+    tuning::CarTuning,
+    AppState, FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+use super::{
+    chat::spawn_chat_panel,
+    connect::{GGRS_CHANNEL, LOBBY_CHANNEL},
+    online::{sanitize_name, PlayerName},
+    settings::{SessionSettings, SharedSettings},
+};
+
+#[derive(Component)]
+pub struct MenuLobbyUI;
+
+#[derive(Component)]
+pub enum MenuLobbyBtn {
+    Ready,
+    Color,
+    Car,
+    Map,
+    Mode,
+    Back,
+}
+
+#[derive(Component)]
+pub struct LobbyPlayerList;
+
+#[derive(Component)]
+pub struct LobbyStatusText;
+
+#[derive(Component)]
+pub struct ReadyBtnText;
+
+#[derive(Component)]
+pub struct LobbyMapText;
+
+/// What every peer tells the others about itself while in the lobby.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LobbyPlayer {
+    pub name: String,
+    /// Index into the player colors.
+    pub color: usize,
+    pub car: CarClass,
+    pub ready: bool,
+    pub map_name: String,
+    /// Hash of the selected map file, everyone has to play on the same one.
+    pub map_hash: u64,
+    pub mode: GameMode,
+    /// Hash of the car tuning file, everyone has to drive the same cars.
+    pub tuning_hash: u64,
+    /// GGRS runs at the same rate everywhere.
+    pub settings: SharedSettings,
+}
+
+/// One player of a [`MatchStart`].
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MatchPlayer {
+    pub name: String,
+    pub color: usize,
+    pub car: CarClass,
+}
+
+/// The match the host is about to play, sent with [`LobbyMessage::Start`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MatchStart {
+    pub map_hash: u64,
+    pub mode: GameMode,
+    pub tuning: CarTuning,
+    /// The session is built with these, not the local ones.
+    pub settings: SharedSettings,
+    /// In handle order, as the sender last heard from them.
+    pub players: Vec<MatchPlayer>,
+}
+
+/// Messages exchanged on the reliable lobby channel before the session starts.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LobbyMessage {
+    /// Sent to new peers and to everyone whenever our state changes.
+    Player(LobbyPlayer),
+    /// Sent by the host once it sees everyone ready, so nobody is left behind
+    /// by someone changing their mind at the last moment. The host is already
+    /// playing, so the others go along with the match it describes, even if
+    /// they see the lobby differently by now.
+    Start(MatchStart),
+    /// Sent to everyone by a peer that cannot play the match of a
+    /// [`LobbyMessage::Start`], so the peers already playing leave it again.
+    Reject(String),
+}
+
+impl LobbyMessage {
+    pub fn to_packet(&self) -> Box<[u8]> {
+        bincode::serialize(self)
+            .expect("Could not serialize lobby message")
+            .into_boxed_slice()
+    }
+
+    pub fn from_packet(packet: &[u8]) -> Option<Self> {
+        bincode::deserialize(packet).ok()
+    }
+}
+
+#[derive(Resource)]
+pub struct Lobby {
+    local: LobbyPlayer,
+    map: Map,
+    /// Everything we could play, the host picks one of these.
+    maps: Vec<Map>,
+    peers: HashMap<PeerId, LobbyPlayer>,
+    /// Connected players in handle order.
+    players: Vec<PlayerType<PeerId>>,
+    /// Why we could not play the match the host started.
+    rejected: Option<String>,
+}
+
+impl Lobby {
+    pub fn is_ready(&self) -> bool {
+        self.local.ready
+    }
+
+    /// Changes our ready state and lets everyone know.
+    pub fn set_ready(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, ready: bool) {
+        self.local.ready = ready;
+        broadcast(socket, &LobbyMessage::Player(self.local.clone()));
+    }
+
+    /// The player with handle 0, the one with the lowest [`PeerId`], picks the map.
+    fn is_host(&self) -> bool {
+        self.players.first().is_none_or(|p| *p == PlayerType::Local)
+    }
+
+    fn host(&self) -> Option<&LobbyPlayer> {
+        self.players.first().and_then(|p| self.player(p))
+    }
+
+    /// Switches to another map, everyone has to confirm they are ready again.
+    fn set_map(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, map: Map) {
+        self.local.map_name = map.name.clone();
+        self.local.map_hash = map.hash;
+        self.map = map;
+        self.set_ready(socket, false);
+    }
+
+    /// Only the host gets to cycle through the modes.
+    fn next_mode(&mut self, socket: &mut MatchboxSocket<MultipleChannels>) {
+        if self.is_host() {
+            let mode = self.local.mode.next();
+            self.set_mode(socket, mode);
+        }
+    }
+
+    fn set_mode(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, mode: GameMode) {
+        self.local.mode = mode;
+        self.set_ready(socket, false);
+    }
+
+    /// The mode of the host, if we are not playing it yet.
+    fn host_mode(&self) -> Option<GameMode> {
+        if self.is_host() {
+            return None;
+        }
+        self.host()
+            .map(|host| host.mode)
+            .filter(|mode| *mode != self.local.mode)
+    }
+
+    /// Only the host gets to cycle through the maps.
+    fn next_map(&mut self, socket: &mut MatchboxSocket<MultipleChannels>) {
+        if !self.is_host() || self.maps.is_empty() {
+            return;
+        }
+        let current = self.maps.iter().position(|m| m.hash == self.map.hash);
+        let next = current.map_or(0, |i| (i + 1) % self.maps.len());
+        let map = self.maps[next].clone();
+        self.set_map(socket, map);
+    }
+
+    /// The map of the host, if we have it and are not on it yet.
+    fn host_map(&self) -> Option<&Map> {
+        if self.is_host() {
+            return None;
+        }
+        let hash = self.host()?.map_hash;
+        if hash == self.map.hash {
+            return None;
+        }
+        self.maps.iter().find(|m| m.hash == hash)
+    }
+
+    /// The host picked a map we do not have, or a different version of it.
+    fn host_map_missing(&self) -> Option<&str> {
+        self.host()
+            .filter(|host| !self.maps.iter().any(|m| m.hash == host.map_hash))
+            .map(|host| host.map_name.as_str())
+    }
+
+    fn player(&self, player_type: &PlayerType<PeerId>) -> Option<&LobbyPlayer> {
+        match player_type {
+            PlayerType::Remote(peer) => self.peers.get(peer),
+            _ => Some(&self.local),
+        }
+    }
+
+    /// Colors have to be unique, on conflicts the player with the lower handle keeps it.
+    fn color_taken(&self, player_type: &PlayerType<PeerId>, color: usize) -> bool {
+        self.players
+            .iter()
+            .take_while(|other| *other != player_type)
+            .filter_map(|other| self.player(other))
+            .any(|other| other.color == color)
+    }
+
+    fn same_map(&self, player: &LobbyPlayer) -> bool {
+        player.map_hash == self.map.hash
+    }
+
+    fn same_mode(&self, player: &LobbyPlayer) -> bool {
+        player.mode == self.local.mode
+    }
+
+    fn same_tuning(&self, player: &LobbyPlayer) -> bool {
+        player.tuning_hash == self.local.tuning_hash
+    }
+
+    fn same_settings(&self, player: &LobbyPlayer) -> bool {
+        player.settings == self.local.settings
+    }
+
+    fn same_game(&self, player: &LobbyPlayer) -> bool {
+        self.same_map(player)
+            && self.same_mode(player)
+            && self.same_tuning(player)
+            && self.same_settings(player)
+    }
+
+    /// Our tuning file changed, everyone has to confirm they are ready again.
+    fn set_tuning(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, tuning_hash: u64) {
+        self.local.tuning_hash = tuning_hash;
+        self.set_ready(socket, false);
+    }
+
+    fn everyone_ready(&self, num_players: usize) -> bool {
+        self.players.len() >= num_players
+            && self.players.iter().all(|player_type| {
+                self.player(player_type).is_some_and(|p| {
+                    p.ready && !self.color_taken(player_type, p.color) && self.same_game(p)
+                })
+            })
+    }
+
+    /// Everyone in handle order, as we last heard from them.
+    fn roster(&self) -> Vec<MatchPlayer> {
+        self.players
+            .iter()
+            .enumerate()
+            .map(|(i, player_type)| {
+                let player = self.player(player_type);
+                MatchPlayer {
+                    name: player.map(|p| p.name.clone()).unwrap_or_default(),
+                    color: player.map_or(i, |p| p.color),
+                    car: player.map(|p| p.car).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// Lets the host and everyone who went along know we are not coming.
+    fn reject(&mut self, socket: &mut MatchboxSocket<MultipleChannels>, reason: &str) {
+        warn!("could not play the match the host started, {reason}");
+        broadcast(socket, &LobbyMessage::Reject(reason.to_owned()));
+        self.rejected = Some(reason.to_owned());
+    }
+
+    fn next_free_color(&self) -> usize {
+        let mut color = self.local.color;
+        for _ in 0..PLAYER_COLORS.len() {
+            color = (color + 1) % PLAYER_COLORS.len();
+            let taken = self.peers.values().any(|p| p.color == color);
+            if !taken {
+                break;
+            }
+        }
+        color
+    }
+}
+
+fn broadcast(socket: &mut MatchboxSocket<MultipleChannels>, message: &LobbyMessage) {
+    let packet = message.to_packet();
+    let peers: Vec<_> = socket.connected_peers().collect();
+    for peer in peers {
+        socket.channel_mut(LOBBY_CHANNEL).send(packet.clone(), peer);
+    }
+}
+
+/// What we picked in the menus before going online.
+#[derive(SystemParam)]
+pub struct MenuPicks<'w> {
+    player_name: Res<'w, PlayerName>,
+    maps: Maps<'w>,
+    mode: Res<'w, SelectedMode>,
+    cars: Res<'w, SelectedCars>,
+    settings: Res<'w, SessionSettings>,
+}
+
+pub fn setup_lobby(
+    mut commands: Commands,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    picks: MenuPicks,
+    tuning: Res<CarTuning>,
+) {
+    let players = socket.players();
+    // start out with the color matching our handle, it cannot be taken yet
+    let handle = players
+        .iter()
+        .position(|p| *p == PlayerType::Local)
+        .unwrap_or_default();
+    // until we know who the host is, we go with our own pick
+    let map = picks.maps.selected().clone();
+    let local = LobbyPlayer {
+        name: sanitize_name(&picks.player_name.0),
+        color: handle % PLAYER_COLORS.len(),
+        car: picks.cars.0[0],
+        ready: false,
+        map_name: map.name.clone(),
+        map_hash: map.hash,
+        mode: picks.mode.0,
+        tuning_hash: tuning.hash,
+        settings: picks.settings.shared(),
+    };
+    broadcast(&mut socket, &LobbyMessage::Player(local.clone()));
+
+    commands.insert_resource(Lobby {
+        local,
+        map,
+        maps: picks.maps.iter().cloned().collect(),
+        peers: HashMap::default(),
+        players,
+        rejected: None,
+    });
+}
+
+pub fn update_lobby(
+    mut commands: Commands,
+    mut state: ResMut<NextState<AppState>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut lobby: ResMut<Lobby>,
+    settings: Res<SessionSettings>,
+    conditions: Res<NetworkConditions>,
+    tuning: Res<CarTuning>,
+) {
+    for (peer, new_state) in socket.update_peers() {
+        match new_state {
+            PeerState::Connected => {
+                info!("peer {peer} joined the lobby");
+                let packet = LobbyMessage::Player(lobby.local.clone()).to_packet();
+                socket.channel_mut(LOBBY_CHANNEL).send(packet, peer);
+            }
+            PeerState::Disconnected => {
+                info!("peer {peer} left the lobby");
+                lobby.peers.remove(&peer);
+            }
+        }
+    }
+
+    let players = socket.players();
+    if lobby.players != players {
+        lobby.players = players;
+    }
+
+    let mut start = None;
+    for (peer, packet) in socket.channel_mut(LOBBY_CHANNEL).receive() {
+        match LobbyMessage::from_packet(&packet) {
+            Some(LobbyMessage::Player(mut player)) if player.color < PLAYER_COLORS.len() => {
+                player.name = sanitize_name(&player.name);
+                player.map_name = sanitize_name(&player.map_name);
+                lobby.peers.insert(peer, player);
+            }
+            // two peers starting at once could each play their own match
+            Some(LobbyMessage::Start(match_start))
+                if lobby.players.first() == Some(&PlayerType::Remote(peer)) =>
+            {
+                start = Some(match_start)
+            }
+            // someone else could not play the match, we are still here
+            Some(LobbyMessage::Reject(_)) => {}
+            _ => warn!("peer {peer} sent an invalid lobby message"),
+        }
+    }
+
+    // the tuning file was edited while waiting
+    if lobby.local.tuning_hash != tuning.hash && start.is_none() {
+        info!("car tuning changed");
+        lobby.set_tuning(&mut socket, tuning.hash);
+    }
+
+    if let Some(map) = lobby.host_map().filter(|_| start.is_none()).cloned() {
+        info!("switching to the map of the host, {}", map.name);
+        lobby.set_map(&mut socket, map);
+    }
+    if let Some(mode) = lobby.host_mode().filter(|_| start.is_none()) {
+        info!("switching to the mode of the host, {}", mode.name());
+        lobby.set_mode(&mut socket, mode);
+    }
+
+    if start.is_none() && lobby.is_host() && lobby.everyone_ready(settings.num_players) {
+        let match_start = MatchStart {
+            map_hash: lobby.map.hash,
+            mode: lobby.local.mode,
+            tuning: tuning.clone(),
+            settings: lobby.local.settings,
+            players: lobby.roster(),
+        };
+        broadcast(&mut socket, &LobbyMessage::Start(match_start.clone()));
+        start = Some(match_start);
+    }
+
+    let Some(start) = start else {
+        return;
+    };
+    // the host only saw us ready on the same map, we have it
+    let Some(map) = lobby
+        .maps
+        .iter()
+        .find(|m| m.hash == start.map_hash)
+        .cloned()
+    else {
+        lobby.reject(&mut socket, "the map is missing");
+        return;
+    };
+    if start.players.len() != lobby.players.len() {
+        lobby.reject(&mut socket, "the number of players differs");
+        return;
+    }
+    let settings = settings.with_shared(start.settings);
+    if !settings.is_valid() {
+        lobby.reject(&mut socket, "the session settings are invalid");
+        return;
+    }
+    commands.insert_resource(map);
+    commands.insert_resource(start.mode);
+    commands.insert_resource(start.tuning);
+    start_session(
+        &mut commands,
+        &mut socket,
+        &lobby,
+        &start.players,
+        &settings,
+        &conditions,
+    );
+    state.set(AppState::RoundOnline);
+}
+
+fn start_session(
+    commands: &mut Commands,
+    socket: &mut MatchboxSocket<MultipleChannels>,
+    lobby: &Lobby,
+    roster: &[MatchPlayer],
+    settings: &SessionSettings,
+    conditions: &NetworkConditions,
+) {
+    // create a new ggrs session
+    let mut sess_build = settings
+        .session_builder()
+        .expect("Invalid session settings");
+
+    // add players
+    let mut handles = Vec::new();
+    for (i, player_type) in lobby.players.iter().enumerate() {
+        if *player_type == PlayerType::Local {
+            handles.push(i);
+        }
+        sess_build = sess_build
+            .add_player(*player_type, i)
+            .expect("Invalid player added.");
+    }
+
+    // start the GGRS session
+    let channel = socket.take_channel(GGRS_CHANNEL).unwrap();
+    let sess = if conditions.is_perfect() {
+        sess_build.start_p2p_session(channel)
+    } else {
+        sess_build.start_p2p_session(SimulatedSocket::new(channel, *conditions))
+    }
+    .expect("Session could not be created.");
+
+    // insert session as resource
+    settings.set_frame_rate(commands);
+    commands.insert_resource(Session::P2P(sess));
+    commands.insert_resource(LocalPlayers(handles));
+    // everyone plays with the roster of the host
+    let names = roster.iter().map(|p| sanitize_name(&p.name)).collect();
+    let colors = roster
+        .iter()
+        .map(|p| p.color % PLAYER_COLORS.len())
+        .collect();
+    commands.insert_resource(PlayerNames::new(names));
+    commands.insert_resource(PlayerColors(colors));
+    commands.insert_resource(PlayerCars(roster.iter().map(|p| p.car).collect()));
+}
+
+/// Leaves an online round that one of the peers could not join, it would wait
+/// for them forever.
+pub fn receive_rejects(
+    mut state: ResMut<NextState<AppState>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+) {
+    for (peer, packet) in socket.channel_mut(LOBBY_CHANNEL).receive() {
+        if let Some(LobbyMessage::Reject(reason)) = LobbyMessage::from_packet(&packet) {
+            warn!("peer {peer} could not play the match, {reason}");
+            state.set(AppState::MenuMain);
+        }
+    }
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
+    // ui camera
+    commands
+        .spawn(Camera2dBundle::default())
+        .insert(MenuLobbyUI);
+
+    let text_style = TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size: 40.0,
+        color: BUTTON_TEXT,
+    };
+    let btn = ButtonBundle {
+        style: Style {
+            width: Val::Px(250.0),
+            height: Val::Px(65.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(16.)),
+            padding: UiRect::all(Val::Px(16.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    };
+
+    // root node
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            background_color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            // connected players, filled in by update_lobby_display
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(LobbyPlayerList);
+
+            // status text
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 32.,
+                        ..text_style.clone()
+                    },
+                ))
+                .insert(LobbyStatusText);
+
+            // map and mode, picked by the host
+            parent
+                .spawn(TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 32.,
+                        ..text_style.clone()
+                    },
+                ))
+                .insert(LobbyMapText);
+
+            // ready button
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section("Ready", text_style.clone()))
+                        .insert(ReadyBtnText);
+                })
+                .insert(MenuLobbyBtn::Ready);
+
+            // color button
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Color", text_style.clone()));
+                })
+                .insert(MenuLobbyBtn::Color);
+
+            // car button, cycles through the classes
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Car", text_style.clone()));
+                })
+                .insert(MenuLobbyBtn::Car);
+
+            // map button, only does something for the host
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Map", text_style.clone()));
+                })
+                .insert(MenuLobbyBtn::Map);
+
+            // mode button, same as the map one
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Mode", text_style.clone()));
+                })
+                .insert(MenuLobbyBtn::Mode);
+
+            // back button
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Back to Menu", text_style.clone()));
+                })
+                .insert(MenuLobbyBtn::Back);
+        })
+        .insert(MenuLobbyUI);
+
+    spawn_chat_panel(&mut commands, &font_assets).insert(MenuLobbyUI);
+}
+
+pub fn update_lobby_display(
+    mut commands: Commands,
+    lobby: Res<Lobby>,
+    settings: Res<SessionSettings>,
+    font_assets: Res<FontAssets>,
+    list_query: Query<Entity, With<LobbyPlayerList>>,
+    mut texts: ParamSet<(
+        Query<&mut Text, With<LobbyStatusText>>,
+        Query<&mut Text, With<ReadyBtnText>>,
+        Query<&mut Text, With<LobbyMapText>>,
+    )>,
+) {
+    if !lobby.is_changed() {
+        return;
+    }
+
+    for list in list_query.iter() {
+        commands.entity(list).despawn_descendants();
+        commands.entity(list).with_children(|parent| {
+            for (handle, player_type) in lobby.players.iter().enumerate() {
+                let line = match lobby.player(player_type) {
+                    Some(player) => {
+                        let name = if player.name.is_empty() {
+                            format!("Player {}", handle + 1)
+                        } else {
+                            player.name.clone()
+                        };
+                        let you = if *player_type == PlayerType::Local {
+                            " (you)"
+                        } else {
+                            ""
+                        };
+                        let ready = if player.ready { "Ready" } else { "Not ready" };
+                        let taken = if lobby.color_taken(player_type, player.color) {
+                            ", color taken"
+                        } else {
+                            ""
+                        };
+                        let map = if lobby.same_map(player) {
+                            String::new()
+                        } else {
+                            format!(", other map ({})", player.map_name)
+                        };
+                        let tuning = if lobby.same_tuning(player) {
+                            ""
+                        } else {
+                            ", other car tuning"
+                        };
+                        let settings = if lobby.same_settings(player) {
+                            ""
+                        } else {
+                            ", other settings"
+                        };
+                        let car = player.car.name();
+                        (
+                            format!("{name}{you} ({car}): {ready}{taken}{map}{tuning}{settings}"),
+                            PLAYER_COLORS[player.color],
+                        )
+                    }
+                    None => ("Joining...".to_owned(), BUTTON_TEXT),
+                };
+                parent.spawn(TextBundle::from_section(
+                    line.0,
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 40.0,
+                        color: line.1,
+                    },
+                ));
+            }
+        });
+    }
+
+    let maps_differ = lobby.peers.values().any(|p| !lobby.same_map(p));
+    let tunings_differ = lobby.peers.values().any(|p| !lobby.same_tuning(p));
+    let settings_differ = lobby.peers.values().any(|p| !lobby.same_settings(p));
+    let status = if let Some(reason) = &lobby.rejected {
+        format!("Could not play the match the host started, {reason}")
+    } else if lobby.players.len() < settings.num_players {
+        "Waiting for players...".to_owned()
+    } else if let Some(name) = lobby.host_map_missing() {
+        format!("You do not have the host's map {name}")
+    } else if maps_differ {
+        "Everyone needs the same map to start".to_owned()
+    } else if tunings_differ {
+        "Everyone needs the same car tuning file to start".to_owned()
+    } else if settings_differ {
+        "Everyone needs the same FPS, max prediction and check distance to start".to_owned()
+    } else if lobby.local.ready {
+        "Waiting for everyone to be ready...".to_owned()
+    } else {
+        "Press Ready when you are".to_owned()
+    };
+    for mut text in texts.p0().iter_mut() {
+        text.sections[0].value = status.clone();
+    }
+
+    let ready_label = if lobby.local.ready {
+        "Not Ready"
+    } else {
+        "Ready"
+    };
+    for mut text in texts.p1().iter_mut() {
+        text.sections[0].value = ready_label.to_owned();
+    }
+
+    let picker = if lobby.is_host() {
+        "you pick"
+    } else {
+        "the host picks"
+    };
+    let map_label = format!(
+        "{} on {} ({picker})",
+        lobby.local.mode.name(),
+        lobby.map.name
+    );
+    for mut text in texts.p2().iter_mut() {
+        text.sections[0].value = map_label.clone();
+    }
+}
+
+pub fn btn_visuals(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuLobbyBtn>),
+    >,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<NextState<AppState>>,
+    mut socket: ResMut<MatchboxSocket<MultipleChannels>>,
+    mut lobby: ResMut<Lobby>,
+    mut interaction_query: Query<(&Interaction, &MenuLobbyBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Pressed = *interaction {
+            match btn {
+                MenuLobbyBtn::Ready => {
+                    let ready = !lobby.is_ready();
+                    lobby.set_ready(&mut socket, ready);
+                    continue;
+                }
+                MenuLobbyBtn::Color => {
+                    // the color is locked in while ready
+                    if lobby.local.ready {
+                        continue;
+                    }
+                    lobby.local.color = lobby.next_free_color();
+                }
+                MenuLobbyBtn::Car => {
+                    // just like the color
+                    if lobby.local.ready {
+                        continue;
+                    }
+                    lobby.local.car = lobby.local.car.next();
+                }
+                MenuLobbyBtn::Map => {
+                    lobby.next_map(&mut socket);
+                    continue;
+                }
+                MenuLobbyBtn::Mode => {
+                    lobby.next_mode(&mut socket);
+                    continue;
+                }
+                MenuLobbyBtn::Back => {
+                    state.set(AppState::MenuMain);
+                    continue;
+                }
+            }
+            broadcast(&mut socket, &LobbyMessage::Player(lobby.local.clone()));
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuLobbyUI>>, mut commands: Commands) {
+    commands.remove_resource::<Lobby>();
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
@@ -1,19 +1,31 @@
-use bevy::{app::AppExit, prelude::*};
-use bevy_ggrs::ggrs::{PlayerType, SessionBuilder};
+use bevy::{app::AppExit, ecs::system::SystemParam, prelude::*};
+use bevy_ggrs::ggrs::PlayerType;
 use bevy_ggrs::{LocalPlayers, Session};
 
 use crate::{
-    AppState, FontAssets, GGRSConfig, ImageAssets, BUTTON_TEXT, CHECK_DISTANCE, FPS,
-    HOVERED_BUTTON, INPUT_DELAY, MAX_PREDICTION, NORMAL_BUTTON, NUM_PLAYERS, PRESSED_BUTTON,
+    car::{PlayerCars, SelectedCars},
+    map::{Map, Maps},
+    mode::{GameMode, SelectedMode},
+    round::{PlayerColors, PlayerNames},
+    AppState, FontAssets, ImageAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
 };
 
+use super::settings::SessionSettings;
+
 #[derive(Component)]
 pub struct MenuMainUI;
 
+#[derive(Component)]
+pub struct ModeBtnText;
+
 #[derive(Component)]
 pub enum MenuMainBtn {
     OnlineMatch,
     LocalMatch,
+    Mode,
+    Maps,
+    Cars,
+    Settings,
     Quit,
 }
 
@@ -21,6 +33,7 @@ pub fn setup_ui(
     mut commands: Commands,
     image_assets: Res<ImageAssets>,
     font_assets: Res<FontAssets>,
+    selected_mode: Res<SelectedMode>,
 ) {
     // ui camera
     commands.spawn(Camera2dBundle::default()).insert(MenuMainUI);
@@ -105,6 +118,116 @@ pub fn setup_ui(
                 })
                 .insert(MenuMainBtn::LocalMatch);
 
+            // game mode button, cycles through the modes
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(350.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn(TextBundle::from_section(
+                            mode_label(selected_mode.0),
+                            TextStyle {
+                                font: font_assets.default_font.clone(),
+                                font_size: 40.0,
+                                color: BUTTON_TEXT,
+                            },
+                        ))
+                        .insert(ModeBtnText);
+                })
+                .insert(MenuMainBtn::Mode);
+
+            // map select button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Maps",
+                        TextStyle {
+                            font: font_assets.default_font.clone(),
+                            font_size: 40.0,
+                            color: BUTTON_TEXT,
+                        },
+                    ));
+                })
+                .insert(MenuMainBtn::Maps);
+
+            // car select button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Cars",
+                        TextStyle {
+                            font: font_assets.default_font.clone(),
+                            font_size: 40.0,
+                            color: BUTTON_TEXT,
+                        },
+                    ));
+                })
+                .insert(MenuMainBtn::Cars);
+
+            // settings button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Settings",
+                        TextStyle {
+                            font: font_assets.default_font.clone(),
+                            font_size: 40.0,
+                            color: BUTTON_TEXT,
+                        },
+                    ));
+                })
+                .insert(MenuMainBtn::Settings);
+
             // quit button
             parent
                 .spawn(ButtonBundle {
@@ -135,6 +258,22 @@ pub fn setup_ui(
         .insert(MenuMainUI);
 }
 
+fn mode_label(mode: GameMode) -> String {
+    format!("Mode: {}", mode.name())
+}
+
+pub fn update_mode_display(
+    selected_mode: Res<SelectedMode>,
+    mut query: Query<&mut Text, With<ModeBtnText>>,
+) {
+    if !selected_mode.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = mode_label(selected_mode.0);
+    }
+}
+
 pub fn btn_visuals(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -156,10 +295,28 @@ pub fn btn_visuals(
     }
 }
 
+/// Everything picked in the menus that a local round is started with, except
+/// for the mode.
+#[derive(SystemParam)]
+pub struct LocalRound<'w> {
+    pub settings: Res<'w, SessionSettings>,
+    maps: Maps<'w>,
+    cars: Res<'w, SelectedCars>,
+}
+
+impl<'w> LocalRound<'w> {
+    pub fn start(&self, commands: &mut Commands, mode: GameMode) {
+        let map = self.maps.selected();
+        create_synctest_session(commands, &self.settings, map, mode, &self.cars);
+    }
+}
+
 pub fn btn_listeners(
     mut exit: EventWriter<AppExit>,
     mut commands: Commands,
     mut state: ResMut<NextState<AppState>>,
+    local_round: LocalRound,
+    mut selected_mode: ResMut<SelectedMode>,
     mut interaction_query: Query<(&Interaction, &MenuMainBtn), Changed<Interaction>>,
 ) {
     for (interaction, btn) in interaction_query.iter_mut() {
@@ -169,9 +326,21 @@ pub fn btn_listeners(
                     state.set(AppState::MenuOnline);
                 }
                 MenuMainBtn::LocalMatch => {
-                    create_synctest_session(&mut commands);
+                    local_round.start(&mut commands, selected_mode.0);
                     state.set(AppState::RoundLocal);
                 }
+                MenuMainBtn::Mode => {
+                    selected_mode.0 = selected_mode.0.next();
+                }
+                MenuMainBtn::Maps => {
+                    state.set(AppState::MenuMaps);
+                }
+                MenuMainBtn::Cars => {
+                    state.set(AppState::MenuCars);
+                }
+                MenuMainBtn::Settings => {
+                    state.set(AppState::MenuSettings);
+                }
                 MenuMainBtn::Quit => {
                     exit.send(AppExit);
                 }
@@ -186,17 +355,18 @@ pub fn cleanup_ui(query: Query<Entity, With<MenuMainUI>>, mut commands: Commands
     }
 }
 
-fn create_synctest_session(commands: &mut Commands) {
-    let mut sess_build = SessionBuilder::<GGRSConfig>::new()
-        .with_num_players(NUM_PLAYERS)
-        .with_max_prediction_window(MAX_PREDICTION)
-        .expect("Invalid prediction window")
-        .with_fps(FPS)
-        .expect("Invalid FPS")
-        .with_input_delay(INPUT_DELAY)
-        .with_check_distance(CHECK_DISTANCE);
-
-    for i in 0..NUM_PLAYERS {
+pub fn create_synctest_session(
+    commands: &mut Commands,
+    settings: &SessionSettings,
+    map: &Map,
+    mode: GameMode,
+    cars: &SelectedCars,
+) {
+    let mut sess_build = settings
+        .session_builder()
+        .expect("Invalid session settings");
+
+    for i in 0..settings.num_players {
         sess_build = sess_build
             .add_player(PlayerType::Local, i)
             .expect("Could not add local player");
@@ -204,6 +374,12 @@ fn create_synctest_session(commands: &mut Commands) {
 
     let sess = sess_build.start_synctest_session().expect("");
 
+    settings.set_frame_rate(commands);
     commands.insert_resource(Session::SyncTest(sess));
-    commands.insert_resource(LocalPlayers((0..NUM_PLAYERS).collect()));
+    commands.insert_resource(LocalPlayers((0..settings.num_players).collect()));
+    commands.insert_resource(PlayerNames::local(settings.num_players));
+    commands.insert_resource(PlayerColors::local(settings.num_players));
+    commands.insert_resource(PlayerCars::local(cars, settings.num_players));
+    commands.insert_resource(map.clone());
+    commands.insert_resource(mode);
 }
@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+
+use crate::{
+    map::{Maps, SelectedMap},
+    AppState, FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON,
+};
+
+#[derive(Component)]
+pub struct MenuMapsUI;
+
+#[derive(Component)]
+pub enum MenuMapsBtn {
+    Map(usize),
+    Back,
+}
+
+pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>, maps: Maps) {
+    // ui camera
+    commands.spawn(Camera2dBundle::default()).insert(MenuMapsUI);
+
+    let text_style = TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size: 40.0,
+        color: BUTTON_TEXT,
+    };
+    let btn = ButtonBundle {
+        style: Style {
+            width: Val::Px(400.0),
+            height: Val::Px(65.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(16.)),
+            padding: UiRect::all(Val::Px(16.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    };
+
+    // root node
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            background_color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            // one button per map, picking one goes straight back to the menu
+            for (i, map) in maps.iter().enumerate() {
+                let label = if i == maps.selected_index() {
+                    format!("> {} <", map.name)
+                } else {
+                    map.name.clone()
+                };
+                parent
+                    .spawn(btn.clone())
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                    })
+                    .insert(MenuMapsBtn::Map(i));
+            }
+
+            // back button
+            parent
+                .spawn(btn.clone())
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Back to Menu", text_style.clone()));
+                })
+                .insert(MenuMapsBtn::Back);
+        })
+        .insert(MenuMapsUI);
+}
+
+pub fn btn_visuals(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuMapsBtn>),
+    >,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<NextState<AppState>>,
+    mut selected: ResMut<SelectedMap>,
+    mut interaction_query: Query<(&Interaction, &MenuMapsBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Pressed = *interaction {
+            match btn {
+                MenuMapsBtn::Map(i) => {
+                    selected.0 = *i;
+                    state.set(AppState::MenuMain);
+                }
+                MenuMapsBtn::Back => {
+                    state.set(AppState::MenuMain);
+                }
+            }
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuMapsUI>>, mut commands: Commands) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
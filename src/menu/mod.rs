@@ -1,4 +1,9 @@
+pub mod cars;
+pub mod chat;
 pub mod connect;
+pub mod lobby;
 pub mod main;
+pub mod maps;
 pub mod online;
+pub mod settings;
 pub mod win;
@@ -1,11 +1,12 @@
 use bevy::prelude::*;
+use rand::Rng;
 
 use crate::{
-    AppState, FontAssets, BUTTON_TEXT, DISABLED_BUTTON, HOVERED_BUTTON, NORMAL_BUTTON,
-    PRESSED_BUTTON,
+    share::Clipboard, AppState, FontAssets, BUTTON_TEXT, DISABLED_BUTTON, HOVERED_BUTTON,
+    NORMAL_BUTTON, PRESSED_BUTTON,
 };
 
-use super::connect::ConnectData;
+use super::{connect::ConnectData, settings::SessionSettings};
 
 #[derive(Component)]
 pub struct MenuOnlineUI;
@@ -13,6 +14,7 @@ pub struct MenuOnlineUI;
 #[derive(Component)]
 pub enum MenuOnlineBtn {
     LobbyMatch,
+    CreateLobby,
     QuickMatch,
     Back,
 }
@@ -23,12 +25,104 @@ pub struct ButtonEnabled(bool);
 #[derive(Component)]
 pub struct LobbyCodeText;
 
+#[derive(Component)]
+pub struct PlayerNameText;
+
 #[derive(Resource)]
 pub struct LobbyID(String);
 
+/// Why the last typed or pasted input was rejected, shown below the lobby code.
+#[derive(Resource, Default)]
+pub struct LobbyIdRejection(Option<String>);
+
+pub const MIN_LOBBY_CODE_LEN: usize = 4;
+pub const MAX_LOBBY_CODE_LEN: usize = 12;
+const GENERATED_LOBBY_CODE_LEN: usize = 6;
+/// Letters and digits that are hard to mix up when read out loud.
+const GENERATED_LOBBY_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const ONLY_ALPHANUMERIC: &str = "Only letters and digits are allowed";
+
+/// Whether players can join a lobby with the given code.
+pub fn is_valid_lobby_code(code: &str) -> bool {
+    lobby_code_problem(code).is_none()
+}
+
+/// Explains what is wrong with a lobby code, if anything.
+fn lobby_code_problem(code: &str) -> Option<String> {
+    if !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Some(ONLY_ALPHANUMERIC.to_owned())
+    } else if code.len() < MIN_LOBBY_CODE_LEN {
+        Some(format!(
+            "The code needs at least {MIN_LOBBY_CODE_LEN} characters"
+        ))
+    } else if code.len() > MAX_LOBBY_CODE_LEN {
+        Some(too_long_message())
+    } else {
+        None
+    }
+}
+
+/// Also shown when typing past the limit.
+fn too_long_message() -> String {
+    format!("The code can have at most {MAX_LOBBY_CODE_LEN} characters")
+}
+
+/// Lobby codes are case-insensitive, so they are always stored in upper case.
+/// Everything but letters and digits is dropped, which keeps the room name
+/// built from the code free of url syntax.
+pub fn sanitize_lobby_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Creates a random code for a new lobby.
+pub fn generate_lobby_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..GENERATED_LOBBY_CODE_LEN)
+        .map(|_| {
+            let i = rng.gen_range(0..GENERATED_LOBBY_CODE_CHARS.len());
+            GENERATED_LOBBY_CODE_CHARS[i] as char
+        })
+        .collect()
+}
+
+/// Upper limit for player names, in characters.
+pub const MAX_NAME_LEN: usize = 12;
+
+/// The name other players see, kept across matches.
+#[derive(Resource, Default)]
+pub struct PlayerName(pub String);
+
+/// Characters allowed in player names.
+pub fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == ' ' || c == '-' || c == '_'
+}
+
+/// Strips everything a player name is not allowed to contain.
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| is_name_char(*c))
+        .take(MAX_NAME_LEN)
+        .collect();
+    name.trim().to_owned()
+}
+
+/// The text field typed characters go to.
+#[derive(Resource, Component, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFocus {
+    #[default]
+    LobbyId,
+    PlayerName,
+}
+
 pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
     // lobby id resource
     commands.insert_resource(LobbyID("".to_owned()));
+    commands.insert_resource(LobbyIdRejection::default());
+    commands.insert_resource(InputFocus::default());
     // ui camera
     commands
         .spawn(Camera2dBundle::default())
@@ -38,7 +132,7 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
     commands
         .spawn(NodeBundle {
             style: Style {
-                flex_direction: FlexDirection::ColumnReverse, 
+                flex_direction: FlexDirection::ColumnReverse,
                 align_items: AlignItems::Center,
                 justify_content: JustifyContent::Center,
                 ..Default::default()
@@ -47,6 +141,42 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
             ..Default::default()
         })
         .with_children(|parent| {
+            // player name text
+            parent
+                .spawn(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        justify_content: JustifyContent::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    text: Text {
+                        sections: vec![
+                            TextSection {
+                                value: "Your name:\n".to_owned(),
+                                style: TextStyle {
+                                    font: font_assets.default_font.clone(),
+                                    font_size: 40.0,
+                                    color: BUTTON_TEXT,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_owned(),
+                                style: TextStyle {
+                                    font: font_assets.default_font.clone(),
+                                    font_size: 40.0,
+                                    color: BUTTON_TEXT,
+                                },
+                            },
+                        ],
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Interaction::default())
+                .insert(InputFocus::PlayerName)
+                .insert(PlayerNameText);
+
             // lobby id text
             parent
                 .spawn(TextBundle {
@@ -58,7 +188,7 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                     text: Text {
                         sections: vec![
                             TextSection {
-                                value: "Enter a 4-digit ID!\n".to_owned(),
+                                value: "Enter a lobby code!\n".to_owned(),
                                 style: TextStyle {
                                     font: font_assets.default_font.clone(),
                                     font_size: 40.0,
@@ -73,11 +203,21 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                                     color: BUTTON_TEXT,
                                 },
                             },
+                            TextSection {
+                                value: "".to_owned(),
+                                style: TextStyle {
+                                    font: font_assets.default_font.clone(),
+                                    font_size: 24.0,
+                                    color: DISABLED_BUTTON,
+                                },
+                            },
                         ],
                         ..Default::default()
                     },
                     ..Default::default()
                 })
+                .insert(Interaction::default())
+                .insert(InputFocus::LobbyId)
                 .insert(LobbyCodeText);
 
             // lobby match button
@@ -108,6 +248,33 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
                 .insert(MenuOnlineBtn::LobbyMatch)
                 .insert(ButtonEnabled(false));
 
+            // create lobby button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Create Lobby",
+                        TextStyle {
+                            font: font_assets.default_font.clone(),
+                            font_size: 40.0,
+                            color: BUTTON_TEXT,
+                        },
+                    ));
+                })
+                .insert(MenuOnlineBtn::CreateLobby);
+
             // quick match button
             parent
                 .spawn(ButtonBundle {
@@ -165,30 +332,159 @@ pub fn setup_ui(mut commands: Commands, font_assets: Res<FontAssets>) {
         .insert(MenuOnlineUI);
 }
 
+pub fn update_input_focus(
+    keys: Res<Input<KeyCode>>,
+    mut focus: ResMut<InputFocus>,
+    field_query: Query<(&Interaction, &InputFocus), Changed<Interaction>>,
+) {
+    if keys.just_pressed(KeyCode::Tab) {
+        *focus = match *focus {
+            InputFocus::LobbyId => InputFocus::PlayerName,
+            InputFocus::PlayerName => InputFocus::LobbyId,
+        };
+    }
+    for (interaction, field) in field_query.iter() {
+        if let Interaction::Pressed = *interaction {
+            *focus = *field;
+        }
+    }
+}
+
+pub fn update_input_focus_display(
+    mut query: Query<(&mut Text, &InputFocus)>,
+    focus: Res<InputFocus>,
+) {
+    if !focus.is_changed() {
+        return;
+    }
+    for (mut text, field) in query.iter_mut() {
+        // highlight the prompt of the focused field
+        text.sections[0].style.color = if *field == *focus {
+            PRESSED_BUTTON
+        } else {
+            BUTTON_TEXT
+        };
+    }
+}
+
+pub fn update_player_name(
+    mut char_evr: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    focus: Res<InputFocus>,
+    mut player_name: ResMut<PlayerName>,
+) {
+    if *focus != InputFocus::PlayerName {
+        char_evr.clear();
+        return;
+    }
+    if ctrl_pressed(&keys) {
+        char_evr.clear();
+        return;
+    }
+    let name = &mut player_name.0;
+    for ev in char_evr.read() {
+        if name.chars().count() < MAX_NAME_LEN && is_name_char(ev.char) {
+            name.push(ev.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        name.pop();
+    }
+}
+
+pub fn update_player_name_display(
+    mut query: Query<&mut Text, With<PlayerNameText>>,
+    player_name: Res<PlayerName>,
+) {
+    for mut text in query.iter_mut() {
+        text.sections[1].value = player_name.0.clone();
+    }
+}
+
 pub fn update_lobby_id(
     mut char_evr: EventReader<ReceivedCharacter>,
     keys: Res<Input<KeyCode>>,
+    focus: Res<InputFocus>,
     mut lobby_id: ResMut<LobbyID>,
+    mut rejection: ResMut<LobbyIdRejection>,
+    mut clipboard: NonSendMut<Clipboard>,
 ) {
+    if *focus != InputFocus::LobbyId {
+        char_evr.clear();
+        return;
+    }
     let lid = &mut lobby_id.0;
+    if ctrl_pressed(&keys) {
+        // shortcuts, not text
+        char_evr.clear();
+        if keys.just_pressed(KeyCode::V) {
+            rejection.0 = paste_lobby_id(lid, &mut clipboard);
+        }
+        return;
+    }
     for ev in char_evr.read() {
-        if lid.len() < 4 && ev.char.is_ascii_digit() {
-            lid.push(ev.char);
+        if ev.char.is_control() {
+            continue;
         }
+        rejection.0 = if !ev.char.is_ascii_alphanumeric() {
+            Some(ONLY_ALPHANUMERIC.to_owned())
+        } else if lid.len() >= MAX_LOBBY_CODE_LEN {
+            Some(too_long_message())
+        } else {
+            lid.push(ev.char.to_ascii_uppercase());
+            None
+        };
     }
     if keys.just_pressed(KeyCode::Back) {
-        let mut chars = lid.chars();
-        chars.next_back();
-        *lid = chars.as_str().to_owned();
+        lid.pop();
+        rejection.0 = None;
+    }
+}
+
+/// Replaces the lobby code with the clipboard contents, returning why that failed.
+fn paste_lobby_id(lid: &mut String, clipboard: &mut Clipboard) -> Option<String> {
+    if !Clipboard::CAN_PASTE {
+        return Some("Pasting does not work in the browser, type the code".to_owned());
+    }
+    let Ok(text) = clipboard.paste() else {
+        return Some("Could not read the clipboard".to_owned());
+    };
+    let code = sanitize_lobby_code(text.trim());
+    if code.is_empty() {
+        return Some("The clipboard does not contain a lobby code".to_owned());
     }
+    // cutting it short would join some other lobby
+    if code.len() > MAX_LOBBY_CODE_LEN {
+        return Some(too_long_message());
+    }
+    *lid = code;
+    None
+}
+
+pub fn ctrl_pressed(keys: &Input<KeyCode>) -> bool {
+    keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ])
 }
 
 pub fn update_lobby_id_display(
     mut query: Query<&mut Text, With<LobbyCodeText>>,
-    lobby_id: ResMut<LobbyID>,
+    lobby_id: Res<LobbyID>,
+    rejection: Res<LobbyIdRejection>,
 ) {
+    let code = &lobby_id.0;
+    // rejected input is more helpful than a complaint about the length
+    let feedback = match &rejection.0 {
+        Some(reason) => reason.clone(),
+        None if code.is_empty() => String::new(),
+        None => lobby_code_problem(code).unwrap_or_else(|| "Press Lobby Match to join".to_owned()),
+    };
     for mut text in query.iter_mut() {
-        text.sections[1].value = lobby_id.0.clone();
+        text.sections[1].value = code.clone();
+        text.sections[2].value = format!("\n{feedback}");
     }
 }
 
@@ -198,7 +494,7 @@ pub fn update_lobby_btn(
 ) {
     let mut lobby_id_complete = false;
     for text in text_query.iter() {
-        if text.sections[1].value.len() == 4 {
+        if is_valid_lobby_code(&text.sections[1].value) {
             lobby_id_complete = true;
             break;
         }
@@ -242,6 +538,8 @@ pub fn btn_listeners(
     mut commands: Commands,
     mut state: ResMut<NextState<AppState>>,
     lobby_id: Res<LobbyID>,
+    settings: Res<SessionSettings>,
+    mut clipboard: NonSendMut<Clipboard>,
     mut interaction_query: Query<
         (&Interaction, &MenuOnlineBtn, Option<&ButtonEnabled>),
         Changed<Interaction>,
@@ -260,15 +558,20 @@ pub fn btn_listeners(
         if let Interaction::Pressed = *interaction {
             match btn {
                 MenuOnlineBtn::LobbyMatch => {
-                    commands.insert_resource(ConnectData {
-                        lobby_id: format!("bevy{}", lobby_id.0),
-                    });
+                    commands.insert_resource(ConnectData::lobby(&lobby_id.0));
+                    state.set(AppState::MenuConnect);
+                }
+                MenuOnlineBtn::CreateLobby => {
+                    let code = generate_lobby_code();
+                    match clipboard.copy(&code) {
+                        Ok(()) => info!("lobby code {code} copied to clipboard"),
+                        Err(e) => warn!("could not copy lobby code to clipboard: {e}"),
+                    }
+                    commands.insert_resource(ConnectData::lobby(&code));
                     state.set(AppState::MenuConnect);
                 }
                 MenuOnlineBtn::QuickMatch => {
-                    commands.insert_resource(ConnectData {
-                        lobby_id: "bevy?next=2".to_owned(),
-                    });
+                    commands.insert_resource(ConnectData::quick_match(settings.num_players));
                     state.set(AppState::MenuConnect);
                 }
                 MenuOnlineBtn::Back => {
@@ -0,0 +1,354 @@
+use bevy::prelude::*;
+use bevy_ggrs::ggrs::{DesyncDetection, GgrsError, SessionBuilder};
+use bevy_ggrs::GgrsApp;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppState, FontAssets, GGRSConfig, BUTTON_TEXT, CHECK_DISTANCE, DESYNC_INTERVAL, FPS,
+    HOVERED_BUTTON, INPUT_DELAY, MAX_PREDICTION, NORMAL_BUTTON, NUM_PLAYERS, PRESSED_BUTTON,
+};
+
+#[derive(Component)]
+pub struct MenuSettingsUI;
+
+#[derive(Component)]
+pub enum MenuSettingsBtn {
+    Decrease(SessionParam),
+    Increase(SessionParam),
+    Back,
+}
+
+#[derive(Component)]
+pub struct SettingsValueText(SessionParam);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionParam {
+    Fps,
+    MaxPrediction,
+    InputDelay,
+    CheckDistance,
+}
+
+impl SessionParam {
+    const ALL: [SessionParam; 4] = [
+        SessionParam::Fps,
+        SessionParam::MaxPrediction,
+        SessionParam::InputDelay,
+        SessionParam::CheckDistance,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            SessionParam::Fps => "FPS",
+            SessionParam::MaxPrediction => "Max Prediction",
+            SessionParam::InputDelay => "Input Delay",
+            SessionParam::CheckDistance => "Check Distance",
+        }
+    }
+
+    /// The lowest value the [`SessionBuilder`] accepts.
+    fn min(&self) -> usize {
+        match self {
+            SessionParam::Fps | SessionParam::MaxPrediction => 1,
+            SessionParam::InputDelay | SessionParam::CheckDistance => 0,
+        }
+    }
+
+    /// Upper bounds only keep the menu sane.
+    fn max(&self) -> usize {
+        match self {
+            SessionParam::Fps => 240,
+            SessionParam::MaxPrediction => 32,
+            SessionParam::InputDelay => 16,
+            SessionParam::CheckDistance => 31,
+        }
+    }
+}
+
+/// Tuning parameters for GGRS sessions, used for both local and online rounds.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionSettings {
+    pub num_players: usize,
+    pub fps: usize,
+    pub max_prediction: usize,
+    pub input_delay: usize,
+    pub check_distance: usize,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            num_players: NUM_PLAYERS,
+            fps: FPS,
+            max_prediction: MAX_PREDICTION,
+            input_delay: INPUT_DELAY,
+            check_distance: CHECK_DISTANCE,
+        }
+    }
+}
+
+/// The part of the [`SessionSettings`] every peer of an online session has to
+/// agree on. The input delay only applies to our own inputs, so it is left out.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SharedSettings {
+    pub fps: usize,
+    pub max_prediction: usize,
+    pub check_distance: usize,
+}
+
+impl SessionSettings {
+    pub fn shared(&self) -> SharedSettings {
+        SharedSettings {
+            fps: self.fps,
+            max_prediction: self.max_prediction,
+            check_distance: self.check_distance,
+        }
+    }
+
+    /// These settings with the shared part replaced, e.g. by the one of the host.
+    pub fn with_shared(&self, shared: SharedSettings) -> Self {
+        Self {
+            fps: shared.fps,
+            max_prediction: shared.max_prediction,
+            check_distance: shared.check_distance,
+            ..*self
+        }
+    }
+
+    pub fn get(&self, param: SessionParam) -> usize {
+        match param {
+            SessionParam::Fps => self.fps,
+            SessionParam::MaxPrediction => self.max_prediction,
+            SessionParam::InputDelay => self.input_delay,
+            SessionParam::CheckDistance => self.check_distance,
+        }
+    }
+
+    fn get_mut(&mut self, param: SessionParam) -> &mut usize {
+        match param {
+            SessionParam::Fps => &mut self.fps,
+            SessionParam::MaxPrediction => &mut self.max_prediction,
+            SessionParam::InputDelay => &mut self.input_delay,
+            SessionParam::CheckDistance => &mut self.check_distance,
+        }
+    }
+
+    /// Creates a [`SessionBuilder`] preconfigured with these settings.
+    pub fn session_builder(&self) -> Result<SessionBuilder<GGRSConfig>, GgrsError> {
+        Ok(SessionBuilder::<GGRSConfig>::new()
+            .with_num_players(self.num_players)
+            .with_max_prediction_window(self.max_prediction)?
+            .with_fps(self.fps)?
+            .with_input_delay(self.input_delay)
+            .with_check_distance(self.check_distance)
+            .with_desync_detection_mode(DesyncDetection::On {
+                interval: DESYNC_INTERVAL,
+            }))
+    }
+
+    /// Has the rollback schedule run at the rate the session is configured with,
+    /// once the commands are applied.
+    pub fn set_frame_rate(&self, commands: &mut Commands) {
+        let fps = self.fps;
+        commands.add(move |world: &mut World| {
+            // bevy_ggrs has no constructor for the RollbackFrameRate, only its app extension
+            let mut app = App::empty();
+            std::mem::swap(world, &mut app.world);
+            app.set_rollback_schedule_fps(fps);
+            std::mem::swap(world, &mut app.world);
+        });
+    }
+
+    /// Whether every parameter is in its range, and SyncTest sessions can check
+    /// far enough back.
+    pub fn is_valid(&self) -> bool {
+        let in_range = SessionParam::ALL
+            .iter()
+            .all(|param| (param.min()..=param.max()).contains(&self.get(*param)));
+        in_range && self.check_distance < self.max_prediction
+    }
+
+    /// Steps a parameter by `delta`, keeping the old value if the result would be rejected.
+    fn step(&mut self, param: SessionParam, delta: isize) {
+        let mut candidate = *self;
+        let value = candidate.get_mut(param);
+        match value.checked_add_signed(delta) {
+            Some(v) => *value = v,
+            None => return,
+        }
+        if candidate.is_valid() {
+            *self = candidate;
+        }
+    }
+}
+
+pub fn setup_ui(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    settings: Res<SessionSettings>,
+) {
+    // ui camera
+    commands
+        .spawn(Camera2dBundle::default())
+        .insert(MenuSettingsUI);
+
+    let text_style = TextStyle {
+        font: font_assets.default_font.clone(),
+        font_size: 40.0,
+        color: BUTTON_TEXT,
+    };
+    let small_btn = ButtonBundle {
+        style: Style {
+            width: Val::Px(65.0),
+            height: Val::Px(65.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(8.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    };
+
+    // root node
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            background_color: Color::NONE.into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            // one row per session parameter
+            for param in SessionParam::ALL {
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn(small_btn.clone())
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section("-", text_style.clone()));
+                            })
+                            .insert(MenuSettingsBtn::Decrease(param));
+
+                        parent
+                            .spawn(TextBundle {
+                                style: Style {
+                                    width: Val::Px(400.0),
+                                    justify_content: JustifyContent::Center,
+                                    ..Default::default()
+                                },
+                                text: Text::from_section(
+                                    format!("{}: {}", param.label(), settings.get(param)),
+                                    text_style.clone(),
+                                )
+                                .with_alignment(TextAlignment::Center),
+                                ..Default::default()
+                            })
+                            .insert(SettingsValueText(param));
+
+                        parent
+                            .spawn(small_btn.clone())
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section("+", text_style.clone()));
+                            })
+                            .insert(MenuSettingsBtn::Increase(param));
+                    });
+            }
+
+            // back button
+            parent
+                .spawn(ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(65.0),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(16.)),
+                        padding: UiRect::all(Val::Px(16.)),
+                        ..Default::default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Back to Menu", text_style.clone()));
+                })
+                .insert(MenuSettingsBtn::Back);
+        })
+        .insert(MenuSettingsUI);
+}
+
+pub fn update_settings_display(
+    mut query: Query<(&mut Text, &SettingsValueText)>,
+    settings: Res<SessionSettings>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (mut text, value_text) in query.iter_mut() {
+        let param = value_text.0;
+        text.sections[0].value = format!("{}: {}", param.label(), settings.get(param));
+    }
+}
+
+pub fn btn_visuals(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuSettingsBtn>),
+    >,
+) {
+    for (interaction, mut color) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_BUTTON.into();
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+pub fn btn_listeners(
+    mut state: ResMut<NextState<AppState>>,
+    mut settings: ResMut<SessionSettings>,
+    mut interaction_query: Query<(&Interaction, &MenuSettingsBtn), Changed<Interaction>>,
+) {
+    for (interaction, btn) in interaction_query.iter_mut() {
+        if let Interaction::Pressed = *interaction {
+            match btn {
+                MenuSettingsBtn::Decrease(param) => {
+                    settings.step(*param, -1);
+                }
+                MenuSettingsBtn::Increase(param) => {
+                    settings.step(*param, 1);
+                }
+                MenuSettingsBtn::Back => {
+                    state.set(AppState::MenuMain);
+                }
+            }
+        }
+    }
+}
+
+pub fn cleanup_ui(query: Query<Entity, With<MenuSettingsUI>>, mut commands: Commands) {
+    for e in query.iter() {
+        commands.entity(e).despawn_recursive();
+    }
+}
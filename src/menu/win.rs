@@ -2,6 +2,8 @@ use bevy::prelude::*;
 
 use crate::{AppState, FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON};
 
+use super::chat::spawn_chat_panel;
+
 #[derive(Component)]
 pub struct WinUI;
 
@@ -13,6 +15,14 @@ pub enum MenuWinBtn {
 #[derive(Resource)]
 pub struct MatchData {
     pub result: String,
+    /// The socket of an earlier online match may still be open after a local
+    /// one, so it does not tell whether there is anyone to chat with.
+    pub online: bool,
+}
+
+/// Whether the match shown on the win screen was played online.
+pub fn online_match(match_data: Option<Res<MatchData>>) -> bool {
+    match_data.is_some_and(|data| data.online)
 }
 
 pub fn setup_ui(mut commands: Commands, match_data: Res<MatchData>, font_assets: Res<FontAssets>) {
@@ -69,7 +79,10 @@ pub fn setup_ui(mut commands: Commands, match_data: Res<MatchData>, font_assets:
         })
         .insert(WinUI);
 
-    commands.remove_resource::<MatchData>();
+    // talk about the match, if it was played online
+    if match_data.online {
+        spawn_chat_panel(&mut commands, &font_assets).insert(WinUI);
+    }
 }
 
 pub fn btn_visuals(
@@ -109,6 +122,7 @@ pub fn btn_listeners(
 }
 
 pub fn cleanup_ui(query: Query<Entity, With<WinUI>>, mut commands: Commands) {
+    commands.remove_resource::<MatchData>();
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
     }
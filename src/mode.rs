@@ -0,0 +1,74 @@
+//! Game modes: the rules played on top of driving around.
+//!
+//! The mode of a round is the [`GameMode`] resource. Every mode comes with a
+//! plugin that registers its rollback systems in [`ModeSystems`], its win
+//! condition and its HUD, all guarded by [`in_mode`]. Adding a mode means adding
+//! a variant and its plugin to [`ModesPlugin`], the rest of the app stays as is.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{combat::CombatPlugin, tag::TagPlugin, AppState};
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameMode {
+    /// No rules and no end, just driving.
+    FreeDrive,
+    #[default]
+    Tag,
+    /// Shoot the other cars, the last one left wins.
+    Combat,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 3] = [GameMode::FreeDrive, GameMode::Tag, GameMode::Combat];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::FreeDrive => "Free Drive",
+            GameMode::Tag => "Tag",
+            GameMode::Combat => "Combat",
+        }
+    }
+
+    /// Accepts the name shown in the menus, in any case and without spaces.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.replace([' ', '-', '_'], "").to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().replace(' ', "").to_lowercase() == name)
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|mode| mode == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+/// The mode picked in the menus, used for the next local round or lobby.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SelectedMode(pub GameMode);
+
+/// Rollback systems of the modes run in here, after the cars moved and before
+/// the frame is counted and checksummed.
+#[derive(SystemSet, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModeSystems;
+
+/// Whether the round is played in `mode`.
+pub fn in_mode(mode: GameMode) -> impl FnMut(Option<Res<GameMode>>) -> bool + Clone {
+    move |current: Option<Res<GameMode>>| current.is_some_and(|current| *current == mode)
+}
+
+/// Whether a local or an online round is being played.
+pub fn in_round() -> impl Condition<()> {
+    in_state(AppState::RoundLocal).or_else(in_state(AppState::RoundOnline))
+}
+
+/// All game modes, added by [`crate::RollbackPlugin`].
+pub struct ModesPlugin;
+
+impl Plugin for ModesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((TagPlugin, CombatPlugin));
+    }
+}
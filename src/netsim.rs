@@ -0,0 +1,105 @@
+//! Simulated bad connections, for seeing rollbacks without a bad network.
+
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{prelude::*, utils::Instant};
+use bevy_ggrs::ggrs::{Message, NonBlockingSocket};
+use bevy_matchbox::prelude::PeerId;
+use rand::Rng;
+
+/// Extra delay for packets picked to arrive out of order.
+const REORDER_DELAY: Duration = Duration::from_millis(50);
+
+/// Applied to every GGRS packet this peer sends and receives, so only one of
+/// the peers has to turn it on.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    pub latency: Duration,
+    /// Up to this much delay is added on top of the latency, at random.
+    pub jitter: Duration,
+    /// Chance of a packet being dropped, between 0 and 1.
+    pub loss: f32,
+    /// Chance of a packet being held back long enough to overtake it.
+    pub reorder: f32,
+}
+
+impl NetworkConditions {
+    pub fn is_perfect(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// When a packet sent now should arrive, `None` if it gets lost.
+    fn delivery_time(&self) -> Option<Instant> {
+        let mut rng = rand::thread_rng();
+        if rng.gen::<f32>() < self.loss {
+            return None;
+        }
+        let mut delay = self.latency + self.jitter.mul_f32(rng.gen());
+        if rng.gen::<f32>() < self.reorder {
+            delay += REORDER_DELAY;
+        }
+        Some(Instant::now() + delay)
+    }
+}
+
+/// Wraps the socket handed to GGRS and holds packets back according to the
+/// [`NetworkConditions`].
+pub struct SimulatedSocket<S> {
+    inner: S,
+    conditions: NetworkConditions,
+    outgoing: VecDeque<(Instant, PeerId, Message)>,
+    incoming: VecDeque<(Instant, PeerId, Message)>,
+}
+
+impl<S: NonBlockingSocket<PeerId>> SimulatedSocket<S> {
+    pub fn new(inner: S, conditions: NetworkConditions) -> Self {
+        Self {
+            inner,
+            conditions,
+            outgoing: VecDeque::new(),
+            incoming: VecDeque::new(),
+        }
+    }
+
+    fn flush_outgoing(&mut self) {
+        for (addr, msg) in take_due(&mut self.outgoing) {
+            self.inner.send_to(&msg, &addr);
+        }
+    }
+}
+
+/// Removes the packets whose time has come, the queues are not sorted.
+fn take_due(queue: &mut VecDeque<(Instant, PeerId, Message)>) -> Vec<(PeerId, Message)> {
+    let now = Instant::now();
+    let mut due = Vec::new();
+    let mut waiting = VecDeque::with_capacity(queue.len());
+    for (time, addr, msg) in queue.drain(..) {
+        if time <= now {
+            due.push((addr, msg));
+        } else {
+            waiting.push_back((time, addr, msg));
+        }
+    }
+    *queue = waiting;
+    due
+}
+
+impl<S: NonBlockingSocket<PeerId>> NonBlockingSocket<PeerId> for SimulatedSocket<S> {
+    fn send_to(&mut self, msg: &Message, addr: &PeerId) {
+        if let Some(time) = self.conditions.delivery_time() {
+            self.outgoing.push_back((time, *addr, msg.clone()));
+        }
+        self.flush_outgoing();
+    }
+
+    fn receive_all_messages(&mut self) -> Vec<(PeerId, Message)> {
+        // GGRS polls every frame, which is when delayed packets go out as well
+        self.flush_outgoing();
+        for (addr, msg) in self.inner.receive_all_messages() {
+            if let Some(time) = self.conditions.delivery_time() {
+                self.incoming.push_back((time, addr, msg));
+            }
+        }
+        take_due(&mut self.incoming)
+    }
+}
@@ -0,0 +1,90 @@
+//! Game data loaded from RON files, the maps and the car tuning.
+//!
+//! Every kind of file parses and checks its contents itself, reading the file
+//! and reporting what went wrong is shared.
+
+use std::{fmt, marker::PhantomData};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+pub trait RonAsset: Asset + Sized {
+    /// Of the files loaded by the [`RonLoader`], e.g. `map.ron`.
+    const EXTENSIONS: &'static [&'static str];
+
+    /// Parses the contents of a file and checks that they make sense.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, RonLoadError>;
+}
+
+#[derive(Debug)]
+pub enum RonLoadError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+    Invalid(String),
+}
+
+impl fmt::Display for RonLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RonLoadError::Io(e) => write!(f, "could not read file: {e}"),
+            RonLoadError::Ron(e) => write!(f, "could not parse file: {e}"),
+            RonLoadError::Invalid(e) => write!(f, "invalid file: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RonLoadError {}
+
+impl From<std::io::Error> for RonLoadError {
+    fn from(e: std::io::Error) -> Self {
+        RonLoadError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for RonLoadError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        RonLoadError::Ron(e)
+    }
+}
+
+/// Hashes the files for the peers to compare. Stable across platforms and builds, unlike the std hasher.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Loads any [`RonAsset`], the asset server reports the path along with errors.
+pub struct RonLoader<A>(PhantomData<fn() -> A>);
+
+impl<A> Default for RonLoader<A> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<A: RonAsset> AssetLoader for RonLoader<A> {
+    type Asset = A;
+    type Settings = ();
+    type Error = RonLoadError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<A, RonLoadError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            A::from_bytes(&bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        A::EXTENSIONS
+    }
+}
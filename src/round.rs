@@ -1,40 +1,68 @@
 use bevy::utils::HashMap;
-use bevy::{math::Vec3Swizzles, prelude::*};
-use bevy_ggrs::ggrs::InputStatus;
+use bevy::{ecs::system::SystemParam, math::Vec3Swizzles, prelude::*};
+use bevy_ggrs::ggrs::{GgrsEvent, InputStatus};
 use bevy_ggrs::{AddRollbackCommandExtension, GgrsTime, LocalInputs, LocalPlayers, PlayerInputs, Rollback, Session};
 use bytemuck::{Pod, Zeroable};
 
 use crate::{FontAssets, BUTTON_TEXT, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON};
 use crate::{
+    arena::{spawn_checkpoints, spawn_obstacles},
+    boost::{spawn_boosts, Boost},
+    car::{CarClass, PlayerCars},
     checksum::Checksum,
+    combat::Health,
+    desync::Desync,
+    map::Map,
     menu::win::MatchData,
-    AppState, GGRSConfig, NUM_PLAYERS,
+    mode::GameMode,
+    menu::settings::SessionSettings,
+    tuning::CarTuning,
+    AppState, GGRSConfig, MAX_PLAYERS,
 };
 
-const INPUT_UP: u8 = 0b0001;
-const INPUT_DOWN: u8 = 0b0010;
-const INPUT_LEFT: u8 = 0b0100;
-const INPUT_RIGHT: u8 = 0b1000;
+pub const INPUT_FIRE: u8 = 0b0001;
+pub const INPUT_HANDBRAKE: u8 = 0b0010;
+
+/// Full scale of the analog axes in [`Input`].
+pub const AXIS_MAX: i8 = i8::MAX;
 
 const BLUE: Color = Color::rgb(0.8, 0.6, 0.2);
 const ORANGE: Color = Color::rgb(0., 0.35, 0.8);
 const MAGENTA: Color = Color::rgb(0.9, 0.2, 0.2);
 const GREEN: Color = Color::rgb(0.35, 0.7, 0.35);
-const PLAYER_COLORS: [Color; 4] = [BLUE, ORANGE, MAGENTA, GREEN];
+pub const PLAYER_COLORS: [Color; MAX_PLAYERS] = [BLUE, ORANGE, MAGENTA, GREEN];
+
+pub const PLAYER_SIZE: f32 = 50.;
 
-const PLAYER_SIZE: f32 = 50.;
-const MOV_SPEED: f32 = 0.1;
-const ROT_SPEED: f32 = 0.05;
-const MAX_SPEED: f32 = 7.5;
-const FRICTION: f32 = 0.98;
-const DRIFT: f32 = 0.95;
-const ARENA_SIZE: f32 = 720.0;
-const CUBE_SIZE: f32 = 0.2;
+/// Up, left, down, right, fire and handbrake of every local player, by handle.
+const KEY_SETS: [[KeyCode; 6]; MAX_PLAYERS] = [
+    [KeyCode::W, KeyCode::A, KeyCode::S, KeyCode::D, KeyCode::Space, KeyCode::ShiftLeft],
+    [KeyCode::Up, KeyCode::Left, KeyCode::Down, KeyCode::Right, KeyCode::ControlRight, KeyCode::ShiftRight],
+    [KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::U, KeyCode::O],
+    [KeyCode::Numpad8, KeyCode::Numpad4, KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad0, KeyCode::NumpadEnter],
+];
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
 pub struct Input {
+    /// Buttons, see [`INPUT_FIRE`] and [`INPUT_HANDBRAKE`].
     pub inp: u8,
+    /// Left is positive.
+    pub steer: i8,
+    /// Forward is positive.
+    pub throttle: i8,
+}
+
+impl Input {
+    /// Axes from -1 to 1 are sent as a byte.
+    pub fn quantize(value: f32) -> i8 {
+        (value.clamp(-1., 1.) * AXIS_MAX as f32).round() as i8
+    }
+
+    /// Turns a quantized axis back into -1 to 1, the same on every peer.
+    pub fn axis(value: i8) -> f32 {
+        (value as f32 / AXIS_MAX as f32).max(-1.)
+    }
 }
 
 #[derive(Default, Component)]
@@ -53,13 +81,64 @@ pub enum RoundBtn {
 #[derive(Component)]
 pub struct RoundEntity;
 
-#[derive(Default, Reflect, Component)]
+#[derive(Component)]
+pub struct NameLabel {
+    pub handle: usize,
+}
+
+/// Display names of all players, indexed by player handle.
+#[derive(Resource, Clone, Debug)]
+pub struct PlayerNames(Vec<String>);
+
+impl PlayerNames {
+    /// Players without a name fall back to their player number.
+    pub fn new(names: Vec<String>) -> Self {
+        let names = names
+            .into_iter()
+            .enumerate()
+            .map(|(handle, name)| {
+                if name.is_empty() {
+                    format!("Player {}", handle + 1)
+                } else {
+                    name
+                }
+            })
+            .collect();
+        Self(names)
+    }
+
+    pub fn local(num_players: usize) -> Self {
+        Self::new(vec![String::new(); num_players])
+    }
+
+    pub fn get(&self, handle: usize) -> &str {
+        &self.0[handle]
+    }
+}
+
+/// Index into [`PLAYER_COLORS`] for every player, indexed by player handle.
+#[derive(Resource, Clone, Debug)]
+pub struct PlayerColors(pub Vec<usize>);
+
+impl PlayerColors {
+    pub fn local(num_players: usize) -> Self {
+        Self((0..num_players).collect())
+    }
+
+    pub fn get(&self, handle: usize) -> Color {
+        PLAYER_COLORS[self.0[handle] % PLAYER_COLORS.len()]
+    }
+}
+
+#[derive(Default, Reflect, Component, Clone, Debug, PartialEq)]
 pub struct Velocity(pub Vec2);
 
-#[derive(Default, Reflect, Component)]
+#[derive(Default, Reflect, Component, Clone, Debug, PartialEq)]
 pub struct CarControls {
-    accel: f32,
-    steer: f32,
+    pub accel: f32,
+    pub steer: f32,
+    pub fire: bool,
+    pub handbrake: bool,
 }
 
 #[derive(Default, Reflect, Hash, Resource)]
@@ -68,114 +147,194 @@ pub struct FrameCount {
     pub frame: u32,
 }
 
-pub fn input(mut commands: Commands, local_players: Res<LocalPlayers>, keyboard_input: Res<bevy::prelude::Input<KeyCode>>) {
+pub fn input(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    keyboard_input: Res<bevy::prelude::Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepad_triggers: Res<Axis<GamepadButton>>,
+    gamepad_buttons: Res<bevy::prelude::Input<GamepadButton>>,
+) {
     let local_players = &local_players.0;
     let mut local_inputs = HashMap::new();
 
-    for handle in local_players.iter() {
+    // gamepads go to the local players in the order they were connected
+    let mut pads: Vec<_> = gamepads.iter().collect();
+    pads.sort_by_key(|pad| pad.id);
+
+    for (i, handle) in local_players.iter().enumerate() {
+        // a single player always gets the first set, whatever their handle online
         let local = local_players.len() > 1;
-        let mut input = 0;
+        let keys = if local { KEY_SETS[handle % KEY_SETS.len()] } else { KEY_SETS[0] };
+        let [up, left, down, right, fire_key, handbrake_key] = keys;
+
+        let (mut steer, mut throttle, mut fire, mut handbrake) = (0., 0., false, false);
+        if let Some(&pad) = pads.get(i) {
+            let trigger = |button| gamepad_triggers.get(GamepadButton::new(pad, button)).unwrap_or_default();
+            steer = -gamepad_axes.get(GamepadAxis::new(pad, GamepadAxisType::LeftStickX)).unwrap_or_default();
+            throttle = trigger(GamepadButtonType::RightTrigger2) - trigger(GamepadButtonType::LeftTrigger2);
+            fire = gamepad_buttons.pressed(GamepadButton::new(pad, GamepadButtonType::South));
+            handbrake = gamepad_buttons.pressed(GamepadButton::new(pad, GamepadButtonType::East));
+        }
 
-        if !local || *handle == 0 {
-            if keyboard_input.pressed(KeyCode::W) {
-                input |= INPUT_UP;
-            }
-            if keyboard_input.pressed(KeyCode::A) {
-                input |= INPUT_LEFT;
-            }
-            if keyboard_input.pressed(KeyCode::S) {
-                input |= INPUT_DOWN;
-            }
-            if keyboard_input.pressed(KeyCode::D) {
-                input |= INPUT_RIGHT;
-            }
-        } else {
-            if keyboard_input.pressed(KeyCode::Up) {
-                input |= INPUT_UP;
-            }
-            if keyboard_input.pressed(KeyCode::Left) {
-                input |= INPUT_LEFT;
-            }
-            if keyboard_input.pressed(KeyCode::Down) {
-                input |= INPUT_DOWN;
-            }
-            if keyboard_input.pressed(KeyCode::Right) {
-                input |= INPUT_RIGHT;
-            }
+        // keys take over from the gamepad, at full scale
+        if let Some(value) = key_axis(&keyboard_input, right, left) {
+            steer = value;
+        }
+        if let Some(value) = key_axis(&keyboard_input, down, up) {
+            throttle = value;
+        }
+        fire |= keyboard_input.pressed(fire_key);
+        handbrake |= keyboard_input.pressed(handbrake_key);
+
+        let mut inp = 0;
+        if fire {
+            inp |= INPUT_FIRE;
+        }
+        if handbrake {
+            inp |= INPUT_HANDBRAKE;
         }
 
-        local_inputs.insert(*handle, Input {inp: input });
+        local_inputs.insert(*handle, Input { inp, steer: Input::quantize(steer), throttle: Input::quantize(throttle) });
     }
 
     commands.insert_resource(LocalInputs::<GGRSConfig>(local_inputs));
 }
 
-pub fn setup_round(mut commands: Commands) {
+/// -1 or 1 depending on which of the keys is held, 0 for both.
+fn key_axis(keyboard_input: &bevy::prelude::Input<KeyCode>, negative: KeyCode, positive: KeyCode) -> Option<f32> {
+    let (negative, positive) = (keyboard_input.pressed(negative), keyboard_input.pressed(positive));
+    (negative || positive).then(|| f32::from(u8::from(positive)) - f32::from(u8::from(negative)))
+}
+
+pub fn setup_round(mut commands: Commands, map: Res<Map>) {
     println!("OH YEAH");
     commands.insert_resource(FrameCount::default());
-    commands
-        .spawn(Camera2dBundle::default())
-        .insert(RoundEntity);
     commands
         .spawn(SpriteBundle {
             transform: Transform::from_xyz(0., 0., 0.),
             sprite: Sprite {
                 color: Color::BLACK,
-                custom_size: Some(Vec2::new(ARENA_SIZE, ARENA_SIZE)),
+                custom_size: Some(map.size()),
                 ..Default::default()
             },
             ..Default::default()
         })
         .insert(RoundEntity);
+    spawn_boosts(&mut commands, &map);
+    spawn_obstacles(&mut commands, &map);
+    spawn_checkpoints(&mut commands, &map);
 }
 
-pub fn spawn_players(mut commands: Commands) {
-    let r = ARENA_SIZE / 4.;
-
-    for handle in 0..NUM_PLAYERS {
-        let rot = handle as f32 / NUM_PLAYERS as f32 * 2. * std::f32::consts::PI;
-        let x = r * rot.cos();
-        let y = r * rot.sin();
-
-        let mut transform = Transform::from_translation(Vec3::new(x, y, 1.));
-        transform.rotate(Quat::from_rotation_z(rot));
+pub fn spawn_players(
+    mut commands: Commands,
+    settings: Res<SessionSettings>,
+    names: Res<PlayerNames>,
+    colors: Res<PlayerColors>,
+    cars: Res<PlayerCars>,
+    font_assets: Res<FontAssets>,
+    map: Res<Map>,
+) {
+    for handle in 0..settings.num_players {
+        let color = colors.get(handle);
+        let car = cars.get(handle);
+        let spawn = map.spawns[handle];
+        let (x, y) = (spawn.x, spawn.y);
+        let transform = spawn.transform();
 
         commands
             .spawn(SpriteBundle {
                 transform,
                 sprite: Sprite {
-                    color: PLAYER_COLORS[handle],
-                    custom_size: Some(Vec2::new(PLAYER_SIZE * 0.5, PLAYER_SIZE)),
+                    color,
+                    custom_size: Some(car.sprite_size()),
                     ..Default::default()
                 },
                 ..Default::default()
             })
             .insert(Player { handle })
+            .insert(car)
             .insert(Velocity::default())
             .insert(CarControls::default())
+            .insert(Boost::default())
             .insert(Checksum::default())
             .add_rollback()
             .insert(RoundEntity);
+
+        commands
+            .spawn(Text2dBundle {
+                text: Text::from_section(
+                    names.get(handle),
+                    TextStyle {
+                        font: font_assets.default_font.clone(),
+                        font_size: 20.0,
+                        color,
+                    },
+                )
+                .with_alignment(TextAlignment::Center),
+                transform: Transform::from_translation(Vec3::new(x, y + car.length(), 2.)),
+                ..Default::default()
+            })
+            .insert(NameLabel { handle })
+            .insert(RoundEntity);
+    }
+}
+
+/// Keeps the name labels above the cars without rotating along with them.
+pub fn update_name_labels(
+    player_query: Query<(&Transform, &Player, &CarClass)>,
+    mut label_query: Query<(&mut Transform, &NameLabel), Without<Player>>,
+) {
+    for (mut label_transform, label) in label_query.iter_mut() {
+        for (player_transform, player, car) in player_query.iter() {
+            if player.handle == label.handle {
+                let pos = player_transform.translation.xy() + Vec2::new(0., car.length());
+                label_transform.translation = pos.extend(2.);
+            }
+        }
     }
 }
 
-pub fn print_p2p_events(mut session: ResMut<Session<GGRSConfig>>) {
+pub fn print_p2p_events(mut session: ResMut<Session<GGRSConfig>>, mut desyncs: EventWriter<Desync>) {
     if let Session::P2P(s) = session.as_mut() {
         for event in s.events() {
             info!("GGRS Event: {:?}", event);
+            if let GgrsEvent::DesyncDetected { frame, local_checksum, remote_checksum, .. } = event {
+                desyncs.send(Desync { frame, local_checksum, remote_checksum });
+            }
         }
     }
 }
 
-pub fn check_win(mut next_state: ResMut<NextState<AppState>>, mut commands: Commands) {
-    let condition = false;
-    let confirmed = false;
+/// Lets the game modes end the round once they have a result.
+#[derive(SystemParam)]
+pub struct RoundEnd<'w, 's> {
+    next_state: ResMut<'w, NextState<AppState>>,
+    commands: Commands<'w, 's>,
+    names: Res<'w, PlayerNames>,
+    session: Res<'w, Session<GGRSConfig>>,
+}
+
+impl<'w, 's> RoundEnd<'w, 's> {
+    /// Whether the inputs up to `frame` are known, so it will not be rolled back.
+    pub fn is_confirmed(&self, frame: u32) -> bool {
+        match self.session.as_ref() {
+            Session::P2P(s) => s.confirmed_frame() >= frame as i32,
+            // every input is local
+            _ => true,
+        }
+    }
 
-    if condition && confirmed {
-        next_state.set(AppState::Win);
-        commands.insert_resource(MatchData {
-            result: "Orange won!".to_owned(),
-        });
+    /// Goes to the win screen, without a winner it is a draw.
+    pub fn declare(&mut self, winner: Option<usize>) {
+        let result = match winner {
+            Some(handle) => format!("{} won!", self.names.get(handle)),
+            None => "Draw!".to_owned(),
+        };
+        let online = matches!(self.session.as_ref(), Session::P2P(_));
+        self.next_state.set(AppState::Win);
+        self.commands.insert_resource(MatchData { result, online });
     }
 }
 
@@ -183,9 +342,14 @@ pub fn cleanup(query: Query<Entity, With<RoundEntity>>, mut commands: Commands)
     commands.remove_resource::<FrameCount>();
     commands.remove_resource::<LocalPlayers>();
     commands.remove_resource::<Session<GGRSConfig>>();
+    commands.remove_resource::<PlayerNames>();
+    commands.remove_resource::<PlayerColors>();
+    commands.remove_resource::<PlayerCars>();
+    commands.remove_resource::<Map>();
+    commands.remove_resource::<GameMode>();
 
     // https://github.com/gschup/bevy_ggrs/issues/93 
-    commands.insert_resource(Time::new_with(GgrsTime::default()));
+    commands.insert_resource(Time::new_with(GgrsTime));
 
     for e in query.iter() {
         commands.entity(e).despawn_recursive();
@@ -291,79 +455,107 @@ pub fn increase_frame_count(mut frame_count: ResMut<FrameCount>) {
 }
 
 pub fn apply_inputs(
-    mut query: Query<(&mut CarControls, &Player)>,
+    mut query: Query<(&mut CarControls, &Player, Option<&Health>)>,
     inputs: Res<PlayerInputs<GGRSConfig>>,
 ) {
-    for (mut c, p) in query.iter_mut() {
+    for (mut c, p, health) in query.iter_mut() {
         let input = match inputs[p.handle].1 {
-            InputStatus::Confirmed => inputs[p.handle].0.inp,
-            InputStatus::Predicted => inputs[p.handle].0.inp,
-            InputStatus::Disconnected => 0, // disconnected players do nothing
-        };
-
-        c.steer = if input & INPUT_LEFT != 0 && input & INPUT_RIGHT == 0 {
-            1.
-        } else if input & INPUT_LEFT == 0 && input & INPUT_RIGHT != 0 {
-            -1.
-        } else {
-            0.
+            InputStatus::Confirmed => inputs[p.handle].0,
+            InputStatus::Predicted => inputs[p.handle].0,
+            InputStatus::Disconnected => Input::zeroed(), // disconnected players do nothing
         };
+        // neither do wrecked cars
+        let input = if health.is_some_and(Health::is_wrecked) { Input::zeroed() } else { input };
 
-        c.accel = if input & INPUT_DOWN != 0 && input & INPUT_UP == 0 {
-            -1.
-        } else if input & INPUT_DOWN == 0 && input & INPUT_UP != 0 {
-            1.
-        } else {
-            0.
-        };
+        c.steer = Input::axis(input.steer);
+        c.accel = Input::axis(input.throttle);
+        c.fire = input.inp & INPUT_FIRE != 0;
+        c.handbrake = input.inp & INPUT_HANDBRAKE != 0;
     }
 }
 
-pub fn update_velocity(mut query: Query<(&Transform, &mut Velocity, &CarControls)>) {
-    for (t, mut v, c) in query.iter_mut() {
+pub fn update_velocity(mut query: Query<(&Transform, &mut Velocity, &CarControls, &Boost, &CarClass)>, tuning: Res<CarTuning>) {
+    for (t, mut v, c, boost, car) in query.iter_mut() {
         let vel = &mut v.0;
+        let class = tuning.class(*car);
+        let speed = boost.factor() * class.speed;
         let up = t.up().xy();
         let right = t.right().xy();
 
         // car drives forward / backward
-        *vel += (c.accel * MOV_SPEED) * up;
+        *vel += (c.accel * tuning.accel * speed) * up;
 
         // very realistic tire friction
         let forward_vel = up * vel.dot(up);
         let right_vel = right * vel.dot(right);
 
-        *vel = forward_vel + right_vel * DRIFT;
+        // the handbrake lets the rear slide out
+        *vel = forward_vel + right_vel * class.drift(tuning.drift(c.handbrake));
         if c.accel.abs() <= 0.0 {
-            *vel *= FRICTION;
+            *vel *= tuning.friction;
         }
 
         // constrain velocity
-        *vel = vel.clamp_length_max(MAX_SPEED);
+        *vel = vel.clamp_length_max(tuning.max_speed * speed);
     }
 }
 
-pub fn move_players(mut query: Query<(&mut Transform, &Velocity, &CarControls), With<Rollback>>) {
-    for (mut t, v, c) in query.iter_mut() {
+pub fn move_players(mut query: Query<(&mut Transform, &Velocity, &CarControls, &CarClass), With<Rollback>>, tuning: Res<CarTuning>) {
+    for (mut t, v, c, car) in query.iter_mut() {
         let vel = &v.0;
         let up = t.up().xy();
+        let max_speed = tuning.max_speed * tuning.class(*car).speed;
 
         // rotate car
-        let rot_factor = (vel.length() / MAX_SPEED).clamp(0.0, 1.0); // cannot rotate while standing still
+        let rot_factor = (vel.length() / max_speed).clamp(0.0, 1.0); // cannot rotate while standing still
+        let rot_speed = tuning.rot_speed(c.handbrake); // and turns sharper with the handbrake
         let rot = if vel.dot(up) >= 0.0 {
-            c.steer * ROT_SPEED * rot_factor
+            c.steer * rot_speed * rot_factor
         } else {
             // negate rotation while driving backwards
-            c.steer * ROT_SPEED * rot_factor * -1.0
+            -c.steer * rot_speed * rot_factor
         };
         t.rotate(Quat::from_rotation_z(rot));
 
         // apply velocity
         t.translation.x += vel.x;
         t.translation.y += vel.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantized_axes_keep_their_full_scale() {
+        assert_eq!(Input::quantize(1.), AXIS_MAX);
+        assert_eq!(Input::quantize(-1.), -AXIS_MAX);
+        assert_eq!(Input::quantize(0.), 0);
+        assert_eq!(Input::axis(AXIS_MAX), 1.);
+        assert_eq!(Input::axis(-AXIS_MAX), -1.);
+        assert_eq!(Input::axis(0), 0.);
+    }
+
+    #[test]
+    fn quantize_clamps_out_of_range_axes() {
+        assert_eq!(Input::quantize(1.5), AXIS_MAX);
+        assert_eq!(Input::quantize(-3.), -AXIS_MAX);
+        assert_eq!(Input::quantize(f32::INFINITY), AXIS_MAX);
+        // never sent by us, but a peer could
+        assert_eq!(Input::axis(i8::MIN), -1.);
+    }
 
-        // constrain cube to plane
-        let bounds = (ARENA_SIZE - CUBE_SIZE) * 0.5;
-        t.translation.x = t.translation.x.clamp(-bounds, bounds);
-        t.translation.y = t.translation.y.clamp(-bounds, bounds);
+    #[test]
+    fn axes_survive_the_round_trip() {
+        let step = 1. / AXIS_MAX as f32;
+        for i in -100..=100 {
+            let value = i as f32 / 100.;
+            let axis = Input::axis(Input::quantize(value));
+            assert!((axis - value).abs() <= step / 2., "{value} came back as {axis}");
+        }
+        for value in i8::MIN + 1..=i8::MAX {
+            assert_eq!(Input::quantize(Input::axis(value)), value);
+        }
     }
 }
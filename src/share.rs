@@ -0,0 +1,75 @@
+//! Helpers for passing lobby codes between players outside of the game.
+
+/// Query parameter carrying the lobby code in share links.
+#[cfg(target_arch = "wasm32")]
+const LOBBY_PARAM: &str = "lobby";
+
+/// The system clipboard, a non-send resource opened on first use.
+///
+/// It stays open for the rest of the run, on X11 the copied text can only be
+/// pasted elsewhere as long as the clipboard it was copied with is around.
+#[derive(Default)]
+pub struct Clipboard {
+    #[cfg(not(target_arch = "wasm32"))]
+    inner: Option<arboard::Clipboard>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clipboard {
+    pub const CAN_PASTE: bool = true;
+
+    fn open(&mut self) -> Result<&mut arboard::Clipboard, String> {
+        let clipboard = match self.inner.take() {
+            Some(clipboard) => clipboard,
+            None => arboard::Clipboard::new().map_err(|e| e.to_string())?,
+        };
+        Ok(self.inner.insert(clipboard))
+    }
+
+    /// Puts the given text on the system clipboard.
+    pub fn copy(&mut self, text: &str) -> Result<(), String> {
+        self.open()?.set_text(text).map_err(|e| e.to_string())
+    }
+
+    /// Reads text from the system clipboard.
+    pub fn paste(&mut self) -> Result<String, String> {
+        self.open()?.get_text().map_err(|e| e.to_string())
+    }
+}
+
+/// Browsers only hand out the clipboard to paste events of page elements, which
+/// the game canvas does not get. Lobby codes are shared as links there instead.
+#[cfg(target_arch = "wasm32")]
+impl Clipboard {
+    pub const CAN_PASTE: bool = false;
+
+    pub fn copy(&mut self, _text: &str) -> Result<(), String> {
+        Err("clipboard access is not supported in the browser".to_owned())
+    }
+
+    pub fn paste(&mut self) -> Result<String, String> {
+        Err("clipboard access is not supported in the browser".to_owned())
+    }
+}
+
+/// A link to this page which joins the given lobby when opened.
+#[cfg(target_arch = "wasm32")]
+pub fn share_url(code: &str) -> Option<String> {
+    let location = web_sys::window()?.location();
+    let origin = location.origin().ok()?;
+    let path = location.pathname().ok()?;
+    Some(format!("{origin}{path}?{LOBBY_PARAM}={code}"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn share_url(_code: &str) -> Option<String> {
+    None
+}
+
+/// The lobby code of the share link the page was opened with, if any.
+#[cfg(target_arch = "wasm32")]
+pub fn lobby_code_from_url() -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+    let params = web_sys::UrlSearchParams::new_with_str(&search).ok()?;
+    params.get(LOBBY_PARAM)
+}
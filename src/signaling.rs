@@ -0,0 +1,220 @@
+//! A minimal matchbox signaling server, so matches can be played and tested
+//! without running `matchbox_server` separately.
+//!
+//! Supports the same room urls the game uses: `/<room>` puts everyone in the
+//! same room, `/<room>?next=<n>` groups the next `n` peers into a fresh room.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::log::{info, warn};
+use matchbox_protocol::{PeerEvent, PeerId, PeerRequest};
+use serde_json::Value;
+use tungstenite::{
+    handshake::server::{Request, Response},
+    Message,
+};
+use url::Url;
+
+/// How long a connection blocks on reading before it forwards queued events.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type RoomId = u64;
+
+#[derive(Default)]
+struct ServerState {
+    rooms: HashMap<RoomId, Vec<PeerId>>,
+    /// Rooms still accepting peers, by the path they were requested with.
+    open_rooms: HashMap<String, RoomId>,
+    peers: HashMap<PeerId, (RoomId, Sender<Message>)>,
+    next_room: RoomId,
+}
+
+impl ServerState {
+    fn join(&mut self, path: &str, next: Option<usize>, peer: PeerId, sender: Sender<Message>) {
+        let key = match next {
+            Some(n) => format!("{path}?next={n}"),
+            None => path.to_owned(),
+        };
+        let room = *self.open_rooms.entry(key.clone()).or_insert_with(|| {
+            self.next_room += 1;
+            self.next_room
+        });
+        let members = self.rooms.entry(room).or_default();
+
+        // the peers already there start the handshakes with the new one
+        for other in members.iter() {
+            self.peers[other]
+                .1
+                .send(event(PeerEvent::NewPeer(peer)))
+                .ok();
+        }
+        members.push(peer);
+        if next.is_some_and(|n| members.len() >= n) {
+            self.open_rooms.remove(&key);
+        }
+        self.peers.insert(peer, (room, sender));
+    }
+
+    fn leave(&mut self, peer: PeerId) {
+        let Some((room, _)) = self.peers.remove(&peer) else {
+            return;
+        };
+        let Some(members) = self.rooms.get_mut(&room) else {
+            return;
+        };
+        members.retain(|p| *p != peer);
+        for other in members.iter() {
+            self.peers[other]
+                .1
+                .send(event(PeerEvent::PeerLeft(peer)))
+                .ok();
+        }
+        if members.is_empty() {
+            self.rooms.remove(&room);
+            self.open_rooms.retain(|_, r| *r != room);
+        }
+    }
+
+    fn signal(&self, sender: PeerId, receiver: PeerId, data: Value) {
+        match self.peers.get(&receiver) {
+            Some((_, tx)) => {
+                tx.send(event(PeerEvent::Signal { sender, data })).ok();
+            }
+            None => warn!("signal from {sender} to unknown peer {receiver}"),
+        }
+    }
+}
+
+fn event(event: PeerEvent<Value>) -> Message {
+    Message::Text(serde_json::to_string(&event).expect("error serializing event"))
+}
+
+/// The address to listen on for a `--matchbox` url, its host and port. Rooms in
+/// the path are ignored, and a bare `host:port` works too.
+pub fn bind_addr(url: &str) -> Result<String, String> {
+    let full = if url.contains("://") {
+        url.to_owned()
+    } else {
+        format!("ws://{url}")
+    };
+    let parsed = Url::parse(&full).map_err(|e| format!("invalid signaling url '{url}': {e}"))?;
+    // there is no TLS here, a wss url has to point at some other server
+    if parsed.scheme() != "ws" {
+        return Err(format!(
+            "the built-in signaling server only serves ws:// urls, not '{url}'"
+        ));
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("the signaling url '{url}' has no host"))?;
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    Ok(format!("{host}:{port}"))
+}
+
+/// Starts the server on a background thread, returning the address it listens on.
+///
+/// Binding to port 0 picks a free port, which is handy for tests.
+pub fn spawn(addr: &str) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let state = Arc::new(Mutex::new(ServerState::default()));
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let state = state.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, state) {
+                    warn!("signaling connection failed: {e}");
+                }
+            });
+        }
+    });
+
+    info!("signaling server listening on ws://{local_addr}");
+    Ok(local_addr)
+}
+
+// tungstenite's own error type, boxing it would buy nothing on a thread per peer
+#[allow(clippy::result_large_err)]
+fn handle_connection(
+    stream: TcpStream,
+    state: Arc<Mutex<ServerState>>,
+) -> Result<(), tungstenite::Error> {
+    let mut uri = None;
+    let mut ws = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+        uri = Some(request.uri().clone());
+        Ok(response)
+    })
+    .map_err(|e| match e {
+        tungstenite::HandshakeError::Failure(e) => e,
+        tungstenite::HandshakeError::Interrupted(_) => tungstenite::Error::ConnectionClosed,
+    })?;
+    ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let uri = uri.expect("handshake without request");
+    let next = uri.query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("next="))
+            .and_then(|n| n.parse().ok())
+    });
+
+    let peer = PeerId(uuid::Uuid::from_u128(rand::random()));
+    let (tx, rx) = channel();
+    ws.send(event(PeerEvent::IdAssigned(peer)))?;
+    state.lock().unwrap().join(uri.path(), next, peer, tx);
+    info!("peer {peer} joined {uri}");
+
+    let result = serve_peer(&mut ws, &rx, &state, peer);
+    state.lock().unwrap().leave(peer);
+    info!("peer {peer} left");
+    result
+}
+
+#[allow(clippy::result_large_err)]
+fn serve_peer(
+    ws: &mut tungstenite::WebSocket<TcpStream>,
+    rx: &Receiver<Message>,
+    state: &Mutex<ServerState>,
+    peer: PeerId,
+) -> Result<(), tungstenite::Error> {
+    loop {
+        while let Ok(message) = rx.try_recv() {
+            ws.send(message)?;
+        }
+
+        let text = match ws.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+            {
+                continue
+            }
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        match serde_json::from_str::<PeerRequest<Value>>(&text) {
+            Ok(PeerRequest::Signal { receiver, data }) => {
+                state.lock().unwrap().signal(peer, receiver, data);
+            }
+            Ok(PeerRequest::KeepAlive) => {}
+            Err(e) => warn!("peer {peer} sent an invalid request: {e}"),
+        }
+    }
+}
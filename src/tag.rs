@@ -0,0 +1,210 @@
+//! Tag: one car is "it" and passes that on by touching another car.
+//!
+//! Whoever spent the fewest frames being it when the time runs out wins. The
+//! car that just passed it on cannot be tagged right back for a moment.
+
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_ggrs::{GgrsApp, GgrsSchedule, Rollback, RollbackFrameRate};
+
+use crate::{
+    arena::cars_touch,
+    car::CarClass,
+    desync::DumpApp,
+    mode::{in_mode, in_round, GameMode, ModeSystems},
+    round::{spawn_players, FrameCount, Player, PlayerNames, RoundEnd, RoundUI},
+    AppState, FontAssets, BUTTON_TEXT,
+};
+
+/// A round of tag lasts this many seconds.
+pub const TAG_ROUND_SECONDS: u32 = 90;
+/// How long the car that just tagged someone is safe from being tagged back.
+const TAG_IMMUNITY_SECONDS: u32 = 1;
+
+/// The durations above in frames, at the frame rate of the session. Online,
+/// that is the one the host started the match with, so every peer counts the
+/// same frames. The timings stay the same for the whole round, they are not
+/// rolled back.
+#[derive(Default, Resource, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TagTimings {
+    pub round_frames: u32,
+    pub immunity_frames: u32,
+}
+
+impl TagTimings {
+    pub fn new(fps: usize) -> Self {
+        let fps = fps.max(1) as u32;
+        Self {
+            round_frames: TAG_ROUND_SECONDS * fps,
+            immunity_frames: TAG_IMMUNITY_SECONDS * fps,
+        }
+    }
+}
+
+#[derive(Default, Reflect, Hash, Component, Clone, Debug, PartialEq)]
+#[reflect(Hash)]
+pub struct Tag {
+    pub it: bool,
+    /// Frames spent being it so far, the fewest win.
+    pub it_frames: u32,
+    /// Frames left until this car can be tagged again.
+    pub immunity: u32,
+}
+
+impl Tag {
+    /// Player 1 starts out as it.
+    pub fn starting(handle: usize) -> Self {
+        Self {
+            it: handle == 0,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct TagHud;
+
+pub struct TagPlugin;
+
+impl Plugin for TagPlugin {
+    fn build(&self, app: &mut App) {
+        let tag = in_mode(GameMode::Tag);
+        app.rollback_component_with_reflect::<Tag>()
+            .checksum_component_with_hash::<Tag>()
+            .dump_component::<Tag>()
+            .add_systems(
+                GgrsSchedule,
+                update_tag.in_set(ModeSystems).run_if(tag.clone()),
+            )
+            .add_systems(
+                Update,
+                (update_tag_hud, check_tag_win)
+                    .run_if(in_round())
+                    .run_if(tag.clone()),
+            );
+        for state in [AppState::RoundLocal, AppState::RoundOnline] {
+            app.add_systems(
+                OnEnter(state.clone()),
+                (
+                    (apply_deferred, add_tags).chain().after(spawn_players),
+                    setup_tag_hud,
+                )
+                    .run_if(tag.clone()),
+            )
+            .add_systems(OnExit(state), cleanup_tag);
+        }
+    }
+}
+
+/// Gives the cars spawned by [`spawn_players`] their tag state.
+pub fn add_tags(
+    mut commands: Commands,
+    frame_rate: Res<RollbackFrameRate>,
+    query: Query<(Entity, &Player)>,
+) {
+    commands.insert_resource(TagTimings::new(**frame_rate));
+    for (car, player) in query.iter() {
+        commands.entity(car).insert(Tag::starting(player.handle));
+    }
+}
+
+/// The next round may not be tag, or run at another frame rate.
+pub fn cleanup_tag(mut commands: Commands) {
+    commands.remove_resource::<TagTimings>();
+}
+
+/// Counts the frames spent being it and passes it on to the first car touched,
+/// until the round is over.
+pub fn update_tag(
+    mut query: Query<(&Transform, &Player, &CarClass, &mut Tag), With<Rollback>>,
+    frame: Res<FrameCount>,
+    timings: Res<TagTimings>,
+) {
+    if frame.frame >= timings.round_frames {
+        return;
+    }
+
+    // query order is not the same on every peer, the lower handle gets tagged first
+    let mut cars: Vec<_> = query.iter_mut().collect();
+    cars.sort_by_key(|(_, p, _, _)| p.handle);
+
+    for (.., tag) in cars.iter_mut() {
+        tag.immunity = tag.immunity.saturating_sub(1);
+        if tag.it {
+            tag.it_frames += 1;
+        }
+    }
+
+    let Some(it) = cars.iter().position(|(.., tag)| tag.it) else {
+        return;
+    };
+    let (it_pos, it_car) = (cars[it].0.translation.xy(), *cars[it].2);
+    let tagged = cars.iter().position(|(t, _, car, tag)| {
+        !tag.it && tag.immunity == 0 && cars_touch(it_pos, it_car, t.translation.xy(), **car)
+    });
+    if let Some(tagged) = tagged {
+        cars[it].3.it = false;
+        cars[it].3.immunity = timings.immunity_frames;
+        cars[tagged].3.it = true;
+    }
+}
+
+/// Once the time is up, the player who was it the least wins, the lower handle
+/// winning ties. The tags stop changing then, so the result is final as soon as
+/// that frame is confirmed.
+pub fn check_tag_win(
+    frame: Res<FrameCount>,
+    timings: Res<TagTimings>,
+    query: Query<(&Player, &Tag)>,
+    mut round_end: RoundEnd,
+) {
+    let end = timings.round_frames;
+    if frame.frame < end || !round_end.is_confirmed(end) {
+        return;
+    }
+    let winner = query
+        .iter()
+        .min_by_key(|(p, tag)| (tag.it_frames, p.handle))
+        .map(|(p, _)| p.handle);
+    round_end.declare(winner);
+}
+
+pub fn setup_tag_hud(mut commands: Commands, font_assets: Res<FontAssets>) {
+    commands
+        .spawn(
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: font_assets.default_font.clone(),
+                    font_size: 32.0,
+                    color: BUTTON_TEXT,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(10.0),
+                left: Val::Px(10.0),
+                ..Default::default()
+            }),
+        )
+        .insert(TagHud)
+        .insert(RoundUI);
+}
+
+pub fn update_tag_hud(
+    frame: Res<FrameCount>,
+    timings: Res<TagTimings>,
+    frame_rate: Res<RollbackFrameRate>,
+    names: Res<PlayerNames>,
+    query: Query<(&Player, &Tag)>,
+    mut hud_query: Query<&mut Text, With<TagHud>>,
+) {
+    let frames_left = timings.round_frames.saturating_sub(frame.frame);
+    let seconds_left = (frames_left as usize).div_ceil((**frame_rate).max(1));
+    let it = query
+        .iter()
+        .find(|(_, tag)| tag.it)
+        .map_or("nobody", |(p, _)| names.get(p.handle));
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = format!("{seconds_left}s left, {it} is it");
+    }
+}
@@ -0,0 +1,236 @@
+//! How the cars drive, loaded from `assets/car.tuning.ron`.
+//!
+//! Every peer has to simulate with the same numbers, so the tuning carries a
+//! hash of its file for the lobby to compare. Changes to the file are picked up
+//! right away, except during online rounds. In local rounds they take over at a
+//! frame boundary, see [`TuningChanges`].
+
+use std::{
+    collections::BTreeMap,
+    hash::{Hash, Hasher},
+};
+
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::*;
+use bevy_ggrs::{RollbackFrameCount, Session};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    car::{CarClass, ClassTuning},
+    ron_asset::{fnv1a, RonAsset, RonLoadError, RonLoader},
+    GGRSConfig,
+};
+
+/// Also inserted as a resource, the rollback systems read it from there. The
+/// resource is rolled back along with the cars.
+///
+/// Online, the peer starting the match sends its tuning along, see
+/// [`MatchStart`](crate::menu::lobby::MatchStart).
+#[derive(Asset, TypePath, Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CarTuning {
+    /// Added to the speed every frame at full throttle.
+    pub accel: f32,
+    /// Radians per frame at full steer and top speed.
+    pub rot_speed: f32,
+    pub max_speed: f32,
+    /// Speed kept per frame while coasting.
+    pub friction: f32,
+    /// Sideways speed kept per frame, the lower the better the tires grip.
+    pub drift: f32,
+    /// Replaces `drift` while the handbrake is held, letting the car slide.
+    pub handbrake_drift: f32,
+    /// Rotation is multiplied by this while the handbrake is held.
+    pub handbrake_rot: f32,
+    /// Every class has to be in here.
+    pub classes: BTreeMap<CarClass, ClassTuning>,
+    /// Of the file the tuning was loaded from, 0 for the built in one. Not
+    /// sent along with the tuning.
+    #[serde(skip)]
+    pub hash: u64,
+}
+
+/// Used until the file is loaded, and by apps without an asset server.
+impl Default for CarTuning {
+    fn default() -> Self {
+        Self {
+            accel: 0.1,
+            rot_speed: 0.05,
+            max_speed: 7.5,
+            friction: 0.98,
+            drift: 0.95,
+            handbrake_drift: 0.995,
+            handbrake_rot: 1.6,
+            classes: BTreeMap::from([
+                (
+                    CarClass::Light,
+                    ClassTuning {
+                        speed: 1.15,
+                        grip: 0.85,
+                        mass: 0.7,
+                    },
+                ),
+                (CarClass::Standard, ClassTuning::default()),
+                (
+                    CarClass::Heavy,
+                    ClassTuning {
+                        speed: 0.85,
+                        grip: 1.3,
+                        mass: 1.6,
+                    },
+                ),
+            ]),
+            hash: 0,
+        }
+    }
+}
+
+impl RonAsset for CarTuning {
+    const EXTENSIONS: &'static [&'static str] = &["tuning.ron"];
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, RonLoadError> {
+        let mut tuning: CarTuning = ron::de::from_bytes(bytes)?;
+        if tuning.max_speed <= 0. {
+            return Err(RonLoadError::Invalid(
+                "the top speed has to be positive".to_owned(),
+            ));
+        }
+        let shares = [tuning.friction, tuning.drift, tuning.handbrake_drift];
+        if shares.iter().any(|share| !(0. ..=1.).contains(share)) {
+            return Err(RonLoadError::Invalid(
+                "friction and drift have to be between 0 and 1".to_owned(),
+            ));
+        }
+        if let Some(class) = CarClass::ALL
+            .iter()
+            .find(|class| !tuning.classes.contains_key(class))
+        {
+            return Err(RonLoadError::Invalid(format!(
+                "the {} class is missing",
+                class.name()
+            )));
+        }
+        let positive = |class: &ClassTuning| {
+            [class.speed, class.grip, class.mass]
+                .iter()
+                .all(|value| *value > 0.)
+        };
+        if !tuning.classes.values().all(positive) {
+            return Err(RonLoadError::Invalid(
+                "speed, grip and mass of a class have to be positive".to_owned(),
+            ));
+        }
+        tuning.hash = fnv1a(bytes);
+        Ok(tuning)
+    }
+}
+
+/// Covers every number the cars drive with, for the rollback checksum. The hash
+/// of the file is left out, it is not sent along with the tuning.
+impl Hash for CarTuning {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let values = [
+            self.accel,
+            self.rot_speed,
+            self.max_speed,
+            self.friction,
+            self.drift,
+            self.handbrake_drift,
+            self.handbrake_rot,
+        ];
+        for value in values {
+            value.to_bits().hash(state);
+        }
+        for (class, tuning) in self.classes.iter() {
+            class.hash(state);
+            for value in [tuning.speed, tuning.grip, tuning.mass] {
+                value.to_bits().hash(state);
+            }
+        }
+    }
+}
+
+impl CarTuning {
+    pub fn class(&self, class: CarClass) -> ClassTuning {
+        self.classes.get(&class).copied().unwrap_or_default()
+    }
+
+    pub fn drift(&self, handbrake: bool) -> f32 {
+        if handbrake {
+            self.handbrake_drift
+        } else {
+            self.drift
+        }
+    }
+
+    pub fn rot_speed(&self, handbrake: bool) -> f32 {
+        if handbrake {
+            self.rot_speed * self.handbrake_rot
+        } else {
+            self.rot_speed
+        }
+    }
+}
+
+pub type TuningLoader = RonLoader<CarTuning>;
+
+#[derive(AssetCollection, Resource)]
+pub struct TuningAssets {
+    #[asset(path = "car.tuning.ron")]
+    pub tuning: Handle<CarTuning>,
+}
+
+/// Tunings reloaded during a round, by the first frame simulated with them.
+///
+/// SyncTest resimulates the last few frames all the time, a frame that first ran
+/// with the old tuning has to run with it again. So a reload does not touch the
+/// resource directly, [`apply_tuning_changes`] switches over inside the rollback
+/// schedule once the frame is reached.
+#[derive(Resource, Default)]
+pub struct TuningChanges(BTreeMap<i32, CarTuning>);
+
+/// Copies the loaded file into the [`CarTuning`] resource whenever it changed,
+/// from the next frame on during a round.
+/// Must not run during online rounds, the peers agreed on a tuning to play with.
+pub fn reload_tuning(
+    assets: Option<Res<TuningAssets>>,
+    tunings: Res<Assets<CarTuning>>,
+    session: Option<Res<Session<GGRSConfig>>>,
+    frame: Res<RollbackFrameCount>,
+    mut tuning: ResMut<CarTuning>,
+    mut changes: ResMut<TuningChanges>,
+) {
+    let Some(loaded) = assets.and_then(|assets| tunings.get(&assets.tuning)) else {
+        return;
+    };
+    let latest = changes.0.values().next_back().unwrap_or(&*tuning);
+    if *latest == *loaded {
+        return;
+    }
+    info!("car tuning loaded");
+    if session.is_some() {
+        let frame: i32 = (*frame).into();
+        changes.0.insert(frame + 1, loaded.clone());
+    } else {
+        *tuning = loaded.clone();
+    }
+}
+
+/// Runs first in the [`bevy_ggrs::GgrsSchedule`].
+pub fn apply_tuning_changes(
+    frame: Res<RollbackFrameCount>,
+    changes: Res<TuningChanges>,
+    mut tuning: ResMut<CarTuning>,
+) {
+    let frame: i32 = (*frame).into();
+    let Some((_, changed)) = changes.0.range(..=frame).next_back() else {
+        return;
+    };
+    if *tuning != *changed {
+        *tuning = changed.clone();
+    }
+}
+
+/// The frames count from 0 again in the next round.
+pub fn reset_tuning_changes(mut changes: ResMut<TuningChanges>) {
+    changes.0.clear();
+}
@@ -0,0 +1,126 @@
+//! Checks that the command line parser reports what is wrong with the arguments.
+
+use bevy_ggrs_demo::{
+    cli::{LaunchMode, LaunchOptions},
+    mode::GameMode,
+};
+
+fn parse(args: &[&str]) -> Result<Option<LaunchOptions>, String> {
+    LaunchOptions::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+fn error(args: &[&str]) -> String {
+    parse(args).expect_err("the arguments should be rejected")
+}
+
+#[test]
+fn parses_a_full_command_line() {
+    let options = parse(&[
+        "--local",
+        "--players",
+        "3",
+        "--game-mode",
+        "Tag",
+        "--input-delay",
+        "4",
+        "--packet-loss",
+        "5",
+    ])
+    .unwrap()
+    .unwrap();
+    assert_eq!(options.mode, Some(LaunchMode::Local));
+    assert_eq!(options.num_players, Some(3));
+    assert_eq!(options.game_mode, Some(GameMode::Tag));
+    assert_eq!(options.input_delay, Some(4));
+    assert_eq!(options.packet_loss, Some(5));
+}
+
+#[test]
+fn help_is_not_an_error() {
+    assert_eq!(parse(&["--local", "--help"]), Ok(None));
+}
+
+#[test]
+fn rejects_a_missing_value() {
+    assert_eq!(error(&["--players"]), "--players requires a value");
+    assert_eq!(
+        error(&["--local", "--matchbox"]),
+        "--matchbox requires a value"
+    );
+}
+
+#[test]
+fn rejects_a_bad_number() {
+    assert_eq!(
+        error(&["--input-delay", "two"]),
+        "--input-delay expects a number, got 'two'"
+    );
+    assert_eq!(
+        error(&["--latency", "-5"]),
+        "--latency expects a number, got '-5'"
+    );
+    assert_eq!(
+        error(&["--reorder", "101"]),
+        "--reorder must be between 0 and 100"
+    );
+}
+
+#[test]
+fn rejects_an_unknown_mode() {
+    assert_eq!(
+        error(&["--game-mode", "racing"]),
+        "unknown game mode 'racing'"
+    );
+    assert_eq!(error(&["--car", "truck"]), "unknown car class 'truck'");
+    assert_eq!(error(&["--camera", "top"]), "unknown camera mode 'top'");
+}
+
+#[test]
+fn rejects_more_than_one_launch_mode() {
+    assert_eq!(
+        error(&["--local", "--quick"]),
+        "only one of --local, --lobby and --quick can be given"
+    );
+}
+
+#[test]
+fn rejects_player_counts_out_of_range() {
+    assert_eq!(
+        error(&["--local", "--players", "5"]),
+        "--players must be between 1 and 4"
+    );
+    // online matches need someone to play against
+    assert_eq!(
+        error(&["--quick", "--players", "1"]),
+        "--players must be between 2 and 4"
+    );
+}
+
+#[test]
+fn rejects_unknown_arguments_and_lobby_codes() {
+    assert_eq!(error(&["--fast"]), "unknown argument '--fast'");
+    assert_eq!(error(&["--lobby", "!!"]), "invalid lobby code '!!'");
+}
+
+#[cfg(feature = "local-signaling")]
+#[test]
+fn hosts_signaling_on_the_host_and_port_of_the_url() {
+    use bevy_ggrs_demo::signaling::bind_addr;
+
+    assert_eq!(
+        bind_addr("ws://127.0.0.1:3536"),
+        Ok("127.0.0.1:3536".to_owned())
+    );
+    assert_eq!(
+        bind_addr("ws://localhost:3536/room"),
+        Ok("localhost:3536".to_owned())
+    );
+    assert_eq!(bind_addr("0.0.0.0:3536"), Ok("0.0.0.0:3536".to_owned()));
+    assert_eq!(bind_addr("ws://[::1]:3536/"), Ok("[::1]:3536".to_owned()));
+    assert_eq!(
+        bind_addr("ws://example.com"),
+        Ok("example.com:80".to_owned())
+    );
+    assert!(bind_addr("wss://match.example.com").is_err());
+    assert!(parse(&["--host-signaling", "--matchbox", "wss://match.example.com"]).is_err());
+}
@@ -0,0 +1,117 @@
+//! Plays a local SyncTest round headless and changes the car tuning midway,
+//! the way saving the tuning file does.
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{LocalInputs, LocalPlayers, ReadInputs, RollbackFrameCount};
+use bevy_ggrs_demo::{
+    car::SelectedCars,
+    map::Map,
+    menu::{main::create_synctest_session, settings::SessionSettings},
+    mode::GameMode,
+    ron_asset::RonAsset,
+    round::{setup_round, spawn_players, Input, AXIS_MAX},
+    tuning::{reload_tuning, CarTuning, TuningAssets},
+    AppState, FontAssets, GGRSConfig, RollbackPlugin,
+};
+
+const RELOAD_FRAME: i32 = 100;
+const TARGET_FRAME: i32 = 300;
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keeps every car turning at full throttle.
+fn circling_input(mut commands: Commands, local_players: Res<LocalPlayers>) {
+    let local_inputs = local_players
+        .0
+        .iter()
+        .map(|handle| {
+            let input = Input {
+                inp: 0,
+                steer: AXIS_MAX / 2,
+                throttle: AXIS_MAX,
+            };
+            (*handle, input)
+        })
+        .collect::<HashMap<_, _>>();
+    commands.insert_resource(LocalInputs::<GGRSConfig>(local_inputs));
+}
+
+fn start_session(mut commands: Commands, settings: Res<SessionSettings>) {
+    let map = Map::from_bytes(include_bytes!("../assets/maps/classic.map.ron")).unwrap();
+    create_synctest_session(
+        &mut commands,
+        &settings,
+        &map,
+        GameMode::FreeDrive,
+        &SelectedCars::default(),
+    );
+}
+
+fn local_app() -> App {
+    let tuning = CarTuning::from_bytes(include_bytes!("../assets/car.tuning.ron")).unwrap();
+    let mut tunings = Assets::<CarTuning>::default();
+    let handle = tunings.add(tuning.clone());
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_state::<AppState>()
+        .add_plugins(RollbackPlugin)
+        .init_resource::<SessionSettings>()
+        .insert_resource(FontAssets {
+            default_font: Handle::default(),
+        })
+        .insert_resource(tuning)
+        .insert_resource(tunings)
+        .insert_resource(TuningAssets { tuning: handle })
+        .add_systems(ReadInputs, circling_input)
+        .add_systems(
+            OnEnter(AppState::RoundLocal),
+            (start_session, apply_deferred, setup_round, spawn_players).chain(),
+        )
+        .add_systems(Update, reload_tuning);
+
+    app.world
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::RoundLocal);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+fn frame(app: &App) -> i32 {
+    (*app.world.resource::<RollbackFrameCount>()).into()
+}
+
+/// Runs the app until it simulated `target`, failing if it gets stuck.
+fn run_until(app: &mut App, target: i32) {
+    let start = Instant::now();
+    while frame(app) < target {
+        app.update();
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "the round got stuck at frame {}",
+            frame(app)
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn local_round_keeps_running_after_a_tuning_reload() {
+    let mut app = local_app();
+    run_until(&mut app, RELOAD_FRAME);
+
+    let handle = app.world.resource::<TuningAssets>().tuning.clone();
+    let mut tunings = app.world.resource_mut::<Assets<CarTuning>>();
+    let reloaded = tunings.get_mut(&handle).unwrap();
+    reloaded.accel *= 1.5;
+    reloaded.max_speed *= 1.5;
+    let reloaded = reloaded.clone();
+
+    run_until(&mut app, TARGET_FRAME);
+    assert_eq!(*app.world.resource::<CarTuning>(), reloaded);
+}
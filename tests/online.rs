@@ -0,0 +1,285 @@
+//! Plays a scripted online match between two headless peers on a local
+//! signaling server and checks that they agree on every confirmed frame.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ggrs::{ConfirmedFrameCount, GgrsSchedule, LocalInputs, LocalPlayers, ReadInputs};
+use bevy_ggrs_demo::{
+    car::{CarClass, PlayerCars, SelectedCars},
+    checksum::{checksum_players, Checksum},
+    desync::{differences, FrameDump, FrameSnapshots},
+    map::{Map, MapAssets, SelectedMap},
+    menu::{
+        chat::Chat,
+        connect::{create_matchbox_socket, update_matchbox_socket, ConnectData, MatchboxAddr},
+        lobby::{setup_lobby, update_lobby, Lobby},
+        online::PlayerName,
+        settings::SessionSettings,
+    },
+    mode::{GameMode, SelectedMode},
+    netsim::NetworkConditions,
+    ron_asset::RonAsset,
+    round::{
+        setup_round, spawn_players, FrameCount, Input, Player, AXIS_MAX, INPUT_FIRE,
+        INPUT_HANDBRAKE,
+    },
+    signaling,
+    tuning::CarTuning,
+    AppState, FontAssets, GGRSConfig, RollbackPlugin,
+};
+use bevy_matchbox::prelude::*;
+
+const TARGET_FRAME: i32 = 400;
+const TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Player checksums by frame, overwritten whenever a frame is resimulated.
+#[derive(Resource, Default)]
+struct ChecksumHistory(BTreeMap<u32, Vec<(usize, u16)>>);
+
+fn record_checksums(
+    frame: Res<FrameCount>,
+    query: Query<(&Player, &Checksum)>,
+    mut history: ResMut<ChecksumHistory>,
+) {
+    let mut checksums: Vec<_> = query.iter().map(|(p, c)| (p.handle, c.value)).collect();
+    checksums.sort();
+    history.0.insert(frame.frame, checksums);
+}
+
+/// Drives in circles, each player with its own rhythm, braking now and then and
+/// firing in bursts.
+fn scripted_input(
+    mut commands: Commands,
+    local_players: Res<LocalPlayers>,
+    mut frame: Local<usize>,
+) {
+    let mut local_inputs = HashMap::new();
+    for handle in local_players.0.iter() {
+        let phase = (*frame / 25 + handle) % 5;
+        // partial values go through the proportional mapping too
+        let (steer, throttle) = match phase {
+            0 => (0, AXIS_MAX),
+            1 => (AXIS_MAX / 2, AXIS_MAX),
+            2 => (-AXIS_MAX, 90),
+            3 => (AXIS_MAX, -AXIS_MAX),
+            _ => (0, 0),
+        };
+        let mut inp = 0;
+        if (*frame / 10 + handle).is_multiple_of(4) {
+            inp |= INPUT_FIRE;
+        }
+        if phase == 2 && (*frame / 5).is_multiple_of(2) {
+            inp |= INPUT_HANDBRAKE;
+        }
+        local_inputs.insert(
+            *handle,
+            Input {
+                inp,
+                steer,
+                throttle,
+            },
+        );
+    }
+    *frame += 1;
+    commands.insert_resource(LocalInputs::<GGRSConfig>(local_inputs));
+}
+
+fn ready_up(mut socket: ResMut<MatchboxSocket<MultipleChannels>>, mut lobby: ResMut<Lobby>) {
+    if !lobby.is_ready() {
+        lobby.set_ready(&mut socket, true);
+    }
+}
+
+/// The asset server is not around to load the maps.
+fn map_assets() -> (Assets<Map>, MapAssets) {
+    let files: [&[u8]; 2] = [
+        include_bytes!("../assets/maps/classic.map.ron"),
+        include_bytes!("../assets/maps/open.map.ron"),
+    ];
+    let mut assets = Assets::default();
+    let maps = files
+        .iter()
+        .map(|bytes| assets.add(Map::from_bytes(bytes).unwrap()))
+        .collect();
+    (assets, MapAssets { maps })
+}
+
+fn peer_app(
+    server: SocketAddr,
+    name: &str,
+    map: usize,
+    mode: GameMode,
+    car: CarClass,
+    conditions: NetworkConditions,
+) -> App {
+    let (maps, map_assets) = map_assets();
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_state::<AppState>()
+        .add_plugins(RollbackPlugin)
+        .init_resource::<SessionSettings>()
+        .init_resource::<Chat>()
+        .insert_resource(conditions)
+        .init_resource::<ChecksumHistory>()
+        .insert_resource(MatchboxAddr(format!("ws://{server}")))
+        .insert_resource(PlayerName(name.to_owned()))
+        .insert_resource(ConnectData::lobby("TEST"))
+        .insert_resource(FontAssets {
+            default_font: Handle::default(),
+        })
+        .insert_resource(maps)
+        .insert_resource(map_assets)
+        .insert_resource(SelectedMap(map))
+        .insert_resource(SelectedMode(mode))
+        .insert_resource(SelectedCars([car; 4]))
+        .insert_resource(CarTuning::from_bytes(include_bytes!("../assets/car.tuning.ron")).unwrap())
+        .add_systems(ReadInputs, scripted_input)
+        .add_systems(GgrsSchedule, record_checksums.after(checksum_players))
+        .add_systems(OnEnter(AppState::MenuConnect), create_matchbox_socket)
+        .add_systems(
+            Update,
+            update_matchbox_socket.run_if(in_state(AppState::MenuConnect)),
+        )
+        .add_systems(OnEnter(AppState::MenuLobby), setup_lobby)
+        .add_systems(
+            Update,
+            (update_lobby, ready_up)
+                .chain()
+                .run_if(in_state(AppState::MenuLobby)),
+        )
+        .add_systems(OnEnter(AppState::RoundOnline), (setup_round, spawn_players));
+
+    app.world
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::MenuConnect);
+    app.finish();
+    app.cleanup();
+    app
+}
+
+fn confirmed_frame(app: &App) -> i32 {
+    if *app.world.resource::<State<AppState>>() != AppState::RoundOnline {
+        return -1;
+    }
+    (*app.world.resource::<ConfirmedFrameCount>()).into()
+}
+
+/// Plays until both peers confirmed [`TARGET_FRAME`], with Bob on the given connection.
+/// They start out with different maps and the given modes selected, the host's
+/// have to win. Alice drives a light car and Bob a heavy one. Returns the
+/// compared snapshots.
+fn play_match(conditions: NetworkConditions, modes: [GameMode; 2]) -> Vec<FrameDump> {
+    let server = signaling::spawn("127.0.0.1:0").expect("could not start signaling server");
+    let mut peers = [
+        peer_app(
+            server,
+            "Alice",
+            0,
+            modes[0],
+            CarClass::Light,
+            NetworkConditions::default(),
+        ),
+        peer_app(server, "Bob", 1, modes[1], CarClass::Heavy, conditions),
+    ];
+
+    let start = Instant::now();
+    let confirmed = loop {
+        for app in peers.iter_mut() {
+            app.update();
+        }
+        let confirmed = peers.iter().map(confirmed_frame).min().unwrap();
+        if confirmed >= TARGET_FRAME {
+            break confirmed;
+        }
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "peers only confirmed frame {confirmed} after {TIMEOUT:?}, states: {:?}",
+            peers
+                .iter()
+                .map(|app| app.world.resource::<State<AppState>>().get().clone())
+                .collect::<Vec<_>>()
+        );
+        thread::sleep(Duration::from_millis(1));
+    };
+
+    let [map_a, map_b] = [0, 1].map(|i| peers[i].world.resource::<Map>().hash);
+    assert_eq!(map_a, map_b, "peers are playing on different maps");
+    let [cars_a, cars_b] = [0, 1].map(|i| peers[i].world.resource::<PlayerCars>().0.clone());
+    assert_eq!(cars_a, cars_b, "peers disagree on the cars");
+    assert!(cars_a.contains(&CarClass::Light) && cars_a.contains(&CarClass::Heavy));
+    let [mode_a, mode_b] = [0, 1].map(|i| *peers[i].world.resource::<GameMode>());
+    assert_eq!(mode_a, mode_b, "peers are playing different modes");
+
+    // the desync dumps have to agree as well, they cover more than the checksums
+    let [snapshots_a, snapshots_b] = peers
+        .iter_mut()
+        .map(|app| app.world.remove_resource::<FrameSnapshots>().unwrap().0)
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap();
+    let compared: Vec<_> = snapshots_a
+        .into_iter()
+        .filter(|(frame, _)| *frame < confirmed)
+        .filter_map(|(frame, a)| {
+            let diffs = differences(&a, snapshots_b.get(&frame)?);
+            assert!(diffs.is_empty(), "dumps of frame {frame} differ: {diffs:?}");
+            Some(a)
+        })
+        .collect();
+    assert!(!compared.is_empty(), "no snapshots to compare");
+
+    let [a, b] = peers.map(|mut app| app.world.remove_resource::<ChecksumHistory>().unwrap().0);
+    // frame count n is the state after ggrs frame n - 1
+    for frame in 1..confirmed as u32 {
+        let (Some(a), Some(b)) = (a.get(&frame), b.get(&frame)) else {
+            panic!("frame {frame} was never simulated");
+        };
+        assert_eq!(a.len(), 2, "frame {frame} is missing players");
+        assert_eq!(a, b, "peers desynced at frame {frame}");
+    }
+
+    // make sure the script actually moved the cars around
+    assert_ne!(a[&1], a[&(confirmed as u32 - 1)]);
+
+    compared
+}
+
+fn bad_connection() -> NetworkConditions {
+    NetworkConditions {
+        latency: Duration::from_millis(40),
+        jitter: Duration::from_millis(20),
+        loss: 0.05,
+        reorder: 0.05,
+    }
+}
+
+#[test]
+fn two_peers_agree_on_checksums() {
+    play_match(
+        NetworkConditions::default(),
+        [GameMode::Tag, GameMode::FreeDrive],
+    );
+}
+
+#[test]
+fn two_peers_agree_on_checksums_over_a_bad_connection() {
+    play_match(bad_connection(), [GameMode::Tag, GameMode::FreeDrive]);
+}
+
+/// Projectiles are spawned and despawned all the time, and more so when rolled back.
+#[test]
+fn two_peers_agree_in_combat_over_a_bad_connection() {
+    let snapshots = play_match(bad_connection(), [GameMode::Combat; 2]);
+    let projectiles = snapshots
+        .iter()
+        .flat_map(|dump| dump.entities.iter())
+        .filter(|entity| entity.components.contains_key("Projectile"))
+        .count();
+    assert!(projectiles > 0, "nobody fired");
+}